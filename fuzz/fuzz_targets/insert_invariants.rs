@@ -0,0 +1,73 @@
+//! Starts from a random index state and applies a random sequence of insertions and removals, checking after each one
+//! that the index agrees with a trivial `HashSet`-based model of the affected bucket.
+//!
+//! Run with `cargo fuzz run insert_invariants`.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use ixa_index::typed_index::{Index, TypeErasedIndex};
+use ixa_index::type_erased_index;
+use ixa_index::hash128::one_shot_128;
+use ixa_index::EntityId;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+  Insert { key: u8, entity_id: EntityId },
+  Remove { key: u8, entity_id: EntityId },
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+  typed: Index<u8>,
+  type_erased: type_erased_index::Index,
+  ops: Vec<Op>,
+}
+
+fuzz_target!(|input: Input| {
+  let Input { mut typed, mut type_erased, ops } = input;
+
+  for op in ops {
+    match op {
+      Op::Insert { key, entity_id } => {
+        let was_present = typed.get(&key).is_some_and(|set| set.contains(&entity_id));
+        let expected_len = typed.get(&key).map_or(0, |set| set.len()) + usize::from(!was_present);
+
+        let (newly_added, len) = typed.insert_entity_with_size(&key, entity_id);
+        assert_eq!(newly_added, !was_present);
+        assert_eq!(len, expected_len);
+        assert!(typed.contains_key(&key));
+        assert!(typed.get(&key).unwrap().contains(&entity_id));
+
+        // Once the bucket exists, inserting through the hash must be a no-op.
+        let hash = one_shot_128(&key);
+        assert_eq!(typed.insert_entity_with_hash(hash, entity_id), Ok(false));
+
+        // The fully type-erased index has no get-or-create, so mirror it through the hash API.
+        match type_erased.get_mut(&key) {
+          Some(set) => { set.insert(entity_id); }
+          None => { type_erased.insert(key, [entity_id].into_iter().collect()); }
+        }
+        assert!(type_erased.get(&key).unwrap().contains(&entity_id));
+      }
+
+      Op::Remove { key, entity_id } => {
+        let was_present = typed.get(&key).is_some_and(|set| set.contains(&entity_id));
+        let remaining = typed.get(&key).map_or(0, |set| set.len()) - usize::from(was_present);
+
+        assert_eq!(typed.remove_entity(&key, entity_id), was_present);
+        assert!(!typed.get(&key).is_some_and(|set| set.contains(&entity_id)));
+        // A removal that empties a set evicts its key.
+        if was_present {
+          assert_eq!(typed.contains_key(&key), remaining > 0);
+        }
+
+        let was_present = type_erased.get(&key).is_some_and(|set| set.contains(&entity_id));
+        assert_eq!(type_erased.remove(&key, entity_id), was_present);
+        assert!(!type_erased.get(&key).is_some_and(|set| set.contains(&entity_id)));
+      }
+    }
+  }
+});
@@ -15,6 +15,16 @@ use syn::{
 };
 
 
+/// Creates the names `__ixa_{prefix}0`, `__ixa_{prefix}1`, ... for the tuple bindings in generated code. The
+/// mixed-site span makes the bindings hygienic, and the prefix keeps them distinct from user identifiers in
+/// diagnostics.
+fn binding_idents(prefix: &str, count: usize) -> Vec<Ident> {
+    (0..count)
+        .map(|i| Ident::new(&format!("__ixa_{}{}", prefix, i), proc_macro2::Span::mixed_site()))
+        .collect()
+}
+
+
 #[proc_macro]
 pub fn sorted_tuple_impl(input: TokenStream) -> TokenStream {
     // Parse the input as a list of types
@@ -26,14 +36,9 @@ pub fn sorted_tuple_impl(input: TokenStream) -> TokenStream {
     let mut sorted_types = original_types.clone();
     sorted_types.sort_by(|a, b| quote!(#a).to_string().cmp(&quote!(#b).to_string()));
 
-    // Create tuple binding names: t0, t1, ...
-    let original_bindings: Vec<_> = (0..original_types.len())
-        .map(|i| syn::Ident::new(&format!("t{}", i), proc_macro2::Span::call_site()))
-        .collect();
-
-    let sorted_bindings: Vec<_> = (0..sorted_types.len())
-        .map(|i| syn::Ident::new(&format!("s{}", i), proc_macro2::Span::call_site()))
-        .collect();
+    // Create tuple binding names: __ixa_t0, __ixa_t1, ...
+    let original_bindings = binding_idents("t", original_types.len());
+    let sorted_bindings = binding_idents("s", sorted_types.len());
 
     // `to_sorted_tuple` expression: map sorted types back to original binding positions
     let to_sorted_exprs: Vec<_> = sorted_types.iter().map(|ty| {
@@ -55,21 +60,24 @@ pub fn sorted_tuple_impl(input: TokenStream) -> TokenStream {
         quote!(#ident)
     }).collect();
 
-    let tuple_type = quote! { ( #( #original_types ),* ) };
-    let sorted_tuple_type = quote! { ( #( #sorted_types ),* ) };
+    // Every element is followed by a comma so that one type produces the tuple `(A,)` rather than the parenthesized
+    // type `(A)`, and no types produce `()`. The same applies to the patterns and expressions below.
+    let tuple_type = quote! { ( #( #original_types, )* ) };
+    let sorted_tuple_type = quote! { ( #( #sorted_types, )* ) };
 
     let expanded = quote! {
+        #[automatically_derived]
         impl SortableTuple for #tuple_type {
             type Sorted = #sorted_tuple_type;
 
             fn to_sorted_tuple(self) -> Self::Sorted {
-                let ( #( #original_bindings ),* ) = self;
-                ( #( #to_sorted_exprs ),* )
+                let ( #( #original_bindings, )* ) = self;
+                ( #( #to_sorted_exprs, )* )
             }
 
             fn from_sorted_tuple(sorted: Self::Sorted) -> Self {
-                let ( #( #sorted_bindings ),* ) = sorted;
-                ( #( #from_sorted_exprs ),* )
+                let ( #( #sorted_bindings, )* ) = sorted;
+                ( #( #from_sorted_exprs, )* )
             }
         }
     };
@@ -129,6 +137,17 @@ pub fn sorted_tag_value_impl(input: TokenStream) -> TokenStream {
 
     assert_eq!(orig_tags.len(), orig_values.len());
 
+    // Tags are identified by their string representation, so two equal tags would make the permutation ambiguous and
+    // silently drop a dimension of the key.
+    for (i, tag) in orig_tags.iter().enumerate() {
+        let tag_string = quote!(#tag).to_string();
+        if orig_tags[..i].iter().any(|earlier| quote!(#earlier).to_string() == tag_string) {
+            return syn::Error::new_spanned(tag, format!("duplicate tag `{}` in `tag_tuple`", tag_string))
+                .to_compile_error()
+                .into();
+        }
+    }
+
     // Compute sorted tags and permutation
     let mut indexed_tags: Vec<_> = orig_tags.iter().enumerate().collect();
     indexed_tags.sort_by_key(|(_, ty)| quote!(#ty).to_string());
@@ -139,12 +158,8 @@ pub fn sorted_tag_value_impl(input: TokenStream) -> TokenStream {
                                                     .collect();
 
     // Generate binding names
-    let t_bindings: Vec<_> = (0..orig_values.len())
-        .map(|i| Ident::new(&format!("t{}", i), proc_macro2::Span::call_site()))
-        .collect();
-    let s_bindings: Vec<_> = (0..orig_values.len())
-        .map(|i| Ident::new(&format!("s{}", i), proc_macro2::Span::call_site()))
-        .collect();
+    let t_bindings = binding_idents("t", orig_values.len());
+    let s_bindings = binding_idents("s", orig_values.len());
 
     // to_sorted: map sorted index to original binding
     let to_sorted_exprs: Vec<_> = indexed_tags
@@ -155,37 +170,102 @@ pub fn sorted_tag_value_impl(input: TokenStream) -> TokenStream {
         })
         .collect();
 
-    // from_sorted: map original index to binding in sorted
-    let from_sorted_exprs: Vec<_> = orig_tags
+    // The position in the sorted tuple of each element of the original tuple
+    let permutation: Vec<usize> = orig_tags
         .iter()
         .map(|ty| {
-            let pos = sorted_tags
+            sorted_tags
                 .iter()
                 .position(|t| quote!(#t).to_string() == quote!(#ty).to_string())
-                .unwrap();
-            let ident = &s_bindings[pos];
+                .unwrap()
+        })
+        .collect();
+
+    // from_sorted: map original index to binding in sorted
+    let from_sorted_exprs: Vec<_> = permutation
+        .iter()
+        .map(|pos| {
+            let ident = &s_bindings[*pos];
             quote!(#ident)
         })
         .collect();
 
-    let tag_type = quote! { ( #( #orig_tags ),* ) };
-    let value_type = quote! { ( #( #orig_values ),* ) };
-    let sorted_tag_type = quote! { ( #( #sorted_tags ),* ) };
-    let reordered_value_type = quote! { ( #( #reordered_value_types ),* ) };
+    // Trailing commas make one-element tuples real tuples, as in `sorted_tuple_impl`.
+    let tag_type = quote! { ( #( #orig_tags, )* ) };
+    let value_type = quote! { ( #( #orig_values, )* ) };
+    let sorted_tag_type = quote! { ( #( #sorted_tags, )* ) };
+    let reordered_value_type = quote! { ( #( #reordered_value_types, )* ) };
 
     let expanded = quote! {
+        #[automatically_derived]
         impl SortByTag<#tag_type> for #value_type {
             type SortedTag = #sorted_tag_type;
             type ReorderedValue = #reordered_value_type;
 
+            const PERMUTATION: &'static [usize] = &[ #( #permutation ),* ];
+
             fn reorder_by_tag(self) -> Self::ReorderedValue {
-                let ( #( #t_bindings ),* ) = self;
-                ( #( #to_sorted_exprs ),* )
+                let ( #( #t_bindings, )* ) = self;
+                ( #( #to_sorted_exprs, )* )
             }
 
             fn unreorder_by_tag(sorted: Self::ReorderedValue) -> Self {
-                let ( #( #s_bindings ),* ) = sorted;
-                ( #( #from_sorted_exprs ),* )
+                let ( #( #s_bindings, )* ) = sorted;
+                ( #( #from_sorted_exprs, )* )
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+
+/// Derives a `{Name}Indexes` struct holding one `Index<FieldType>` per field of the struct `Name`, plus an
+/// `insert(&mut self, entity_id, &Name)` that inserts the entity into every field's index under that field's value.
+///
+/// The generated code names `Index` and `EntityId` unqualified, so both must be in scope where the derive is used.
+#[proc_macro_derive(Indexable)]
+pub fn derive_indexable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    let name = &input.ident;
+    let vis = &input.vis;
+
+    if !input.generics.params.is_empty() {
+        return syn::Error::new_spanned(&input.generics, "`Indexable` cannot be derived for generic structs")
+            .to_compile_error()
+            .into();
+    }
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct { fields: syn::Fields::Named(fields), .. }) => &fields.named,
+        _ => {
+            return syn::Error::new_spanned(name, "`Indexable` can only be derived for structs with named fields")
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let indexes_name = Ident::new(&format!("{}Indexes", name), name.span());
+    let field_names: Vec<_> = fields.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+    let field_visibilities: Vec<_> = fields.iter().map(|field| &field.vis).collect();
+    let field_types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let doc = format!("One index per field of `{}`, generated by `#[derive(Indexable)]`.", name);
+
+    let expanded = quote! {
+        #[doc = #doc]
+        #[derive(Default)]
+        #vis struct #indexes_name {
+            #( #field_visibilities #field_names: Index<#field_types>, )*
+        }
+
+        #[automatically_derived]
+        impl #indexes_name {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            /// Inserts `entity_id` into every field's index under the corresponding field of `value`.
+            pub fn insert(&mut self, entity_id: EntityId, value: &#name) {
+                #( let _ = self.#field_names.insert_entity(&value.#field_names, entity_id); )*
             }
         }
     };
@@ -0,0 +1,11 @@
+//! Compile tests for the macro output. Regenerate the expected `.stderr` files with `TRYBUILD=overwrite`.
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/sorted_tuple_empty.rs");
+    t.pass("tests/ui/sorted_tuple_trailing_comma.rs");
+    t.pass("tests/ui/indexable_fields.rs");
+    t.compile_fail("tests/ui/sorted_tag_value_duplicate_tag.rs");
+    t.compile_fail("tests/ui/indexable_tuple_struct.rs");
+}
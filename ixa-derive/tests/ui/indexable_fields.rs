@@ -0,0 +1,39 @@
+// `#[derive(Indexable)]` generates one index per field and routes each field of an inserted value to its index.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use ixa_derive::Indexable;
+
+type EntityId = u64;
+
+// A minimal stand-in for `ixa_index::typed_index::Index`.
+struct Index<T> {
+    lookup: HashMap<T, HashSet<EntityId>>,
+}
+
+impl<T> Default for Index<T> {
+    fn default() -> Self {
+        Self { lookup: HashMap::new() }
+    }
+}
+
+impl<T: Hash + Eq + Clone> Index<T> {
+    fn insert_entity(&mut self, key: &T, entity_id: EntityId) -> bool {
+        self.lookup.entry(key.clone()).or_default().insert(entity_id)
+    }
+}
+
+#[derive(Indexable)]
+struct Person {
+    age: u8,
+    region: String,
+}
+
+fn main() {
+    let mut indexes = PersonIndexes::new();
+    indexes.insert(1, &Person { age: 30, region: "north".to_string() });
+    indexes.insert(2, &Person { age: 30, region: "south".to_string() });
+
+    assert_eq!(indexes.age.lookup[&30], HashSet::from([1, 2]));
+    assert_eq!(indexes.region.lookup["north"], HashSet::from([1]));
+}
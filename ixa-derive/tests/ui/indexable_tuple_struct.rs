@@ -0,0 +1,6 @@
+use ixa_derive::Indexable;
+
+#[derive(Indexable)]
+struct Person(u8, String);
+
+fn main() {}
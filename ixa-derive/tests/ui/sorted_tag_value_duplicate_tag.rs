@@ -0,0 +1,23 @@
+// A tag that appears twice is rejected, naming the duplicated tag.
+
+use ixa_derive::sorted_tag_value_impl;
+
+trait SortByTag<Tag> {
+    type SortedTag;
+    type ReorderedValue;
+
+    const PERMUTATION: &'static [usize];
+
+    fn reorder_by_tag(self) -> Self::ReorderedValue;
+    fn unreorder_by_tag(sorted: Self::ReorderedValue) -> Self;
+}
+
+struct TagA;
+struct TagB;
+
+sorted_tag_value_impl!(
+    tag_tuple = (TagA, TagA, TagB),
+    value_tuple = (u8, u16, u32)
+);
+
+fn main() {}
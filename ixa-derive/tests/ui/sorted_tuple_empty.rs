@@ -0,0 +1,18 @@
+// An empty type list produces a trivially sorted impl for the unit type.
+
+use ixa_derive::sorted_tuple_impl;
+
+trait SortableTuple {
+    type Sorted;
+
+    fn to_sorted_tuple(self) -> Self::Sorted;
+    fn from_sorted_tuple(sorted: Self::Sorted) -> Self;
+}
+
+sorted_tuple_impl!();
+
+fn main() {
+    let sorted: () = ().to_sorted_tuple();
+    let unsorted: () = SortableTuple::from_sorted_tuple(sorted);
+    assert_eq!(unsorted, ());
+}
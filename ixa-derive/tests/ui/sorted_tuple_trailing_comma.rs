@@ -0,0 +1,24 @@
+// A single type with a trailing comma produces an impl for the one-element tuple `(u8,)`, not for `u8`.
+
+use ixa_derive::sorted_tuple_impl;
+
+trait SortableTuple {
+    type Sorted;
+
+    fn to_sorted_tuple(self) -> Self::Sorted;
+    fn from_sorted_tuple(sorted: Self::Sorted) -> Self;
+}
+
+sorted_tuple_impl!(u8,);
+
+// A second impl for two types with a trailing comma must not conflict with the first.
+sorted_tuple_impl!(u16, bool,);
+
+fn main() {
+    let sorted: (u8,) = (7u8,).to_sorted_tuple();
+    assert_eq!(sorted, (7,));
+    let unsorted: (u8,) = SortableTuple::from_sorted_tuple(sorted);
+    assert_eq!(unsorted, (7,));
+
+    assert_eq!((1u16, true).to_sorted_tuple(), (true, 1u16));
+}
@@ -0,0 +1,73 @@
+/*!
+
+An index for fixed-arity composite keys whose components all share a type, e.g. a triple of `u32` region codes.
+
+The key is a `[T; N]` in a fixed order and is hashed as a whole with `one_shot_128`, so none of the tuple sorting
+machinery in `multi_index` is needed. The component order is part of the key: `[1, 2]` and `[2, 1]` are different keys.
+
+*/
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::hash::Hash;
+use crate::typed_index::Index;
+use crate::EntityId;
+
+/// An index keyed by arrays of `N` values of type `T`.
+#[derive(Default, Debug)]
+pub struct ArrayKeyIndex<const N: usize, T: Hash + Eq + Clone + Any> {
+  index: Index<[T; N]>,
+}
+
+impl<const N: usize, T: Hash + Eq + Clone + Any> ArrayKeyIndex<N, T> {
+  pub fn new() -> Self {
+    Self {
+      index: Index::new(),
+    }
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set.
+  #[must_use]
+  pub fn insert_entity(&mut self, key: [T; N], entity_id: EntityId) -> bool {
+    // We own the key, so a new set takes it without cloning.
+    self.index.insert_entity_cow(Cow::Owned(key), entity_id)
+  }
+
+  /// Gets an immutable reference to the set associated with the `key` if it exists.
+  pub fn get(&self, key: [T; N]) -> Option<&HashSet<EntityId>> {
+    self.index.get(&key)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pair_keys() {
+    let mut index: ArrayKeyIndex<2, u32> = ArrayKeyIndex::new();
+    assert!(index.insert_entity([1, 2], 10));
+    assert!(index.insert_entity([1, 2], 11));
+    assert!(!index.insert_entity([1, 2], 11));
+    assert!(index.insert_entity([2, 1], 12));
+
+    assert_eq!(index.get([1, 2]), Some(&HashSet::from([10, 11])));
+    // Component order is significant.
+    assert_eq!(index.get([2, 1]), Some(&HashSet::from([12])));
+    assert_eq!(index.get([2, 2]), None);
+  }
+
+  #[test]
+  fn triple_keys() {
+    let mut index: ArrayKeyIndex<3, u32> = ArrayKeyIndex::new();
+    let _ = index.insert_entity([4, 5, 6], 1);
+    let _ = index.insert_entity([4, 5, 7], 2);
+    let _ = index.insert_entity([4, 5, 6], 3);
+
+    assert_eq!(index.get([4, 5, 6]), Some(&HashSet::from([1, 3])));
+    assert_eq!(index.get([4, 5, 7]), Some(&HashSet::from([2])));
+    assert_eq!(index.get([6, 5, 4]), None);
+  }
+}
@@ -0,0 +1,125 @@
+/*!
+
+A typed index that never holds empty sets.
+
+`Index<T>::get_mut` hands out the set itself, so a caller can empty it and leave an empty bucket behind. Here `get_mut`
+instead returns a `PruneGuard` that derefs to the set and, when dropped, removes the bucket if the set is empty. The
+no-empty-sets invariant is thereby enforced by the type rather than by the caller's discipline.
+
+*/
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
+use hashbrown::hash_table::OccupiedEntry;
+use crate::typed_index::Index;
+use crate::EntityId;
+
+type PersonId = EntityId;
+
+/// A typed index that removes a key as soon as its set becomes empty.
+#[derive(Default, Debug)]
+pub struct AutoPruneIndex<T: Hash + Eq + Clone + Any> {
+  index: Index<T>,
+}
+
+impl<T: Hash + Eq + Clone + Any> AutoPruneIndex<T> {
+  pub fn new() -> Self {
+    Self {
+      index: Index::new(),
+    }
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set.
+  #[must_use]
+  pub fn insert_entity(&mut self, key: &T, entity_id: PersonId) -> bool {
+    self.index.insert_entity(key, entity_id)
+  }
+
+  /// Gets an immutable reference to the set associated with the `key` if it exists. The set is never empty.
+  pub fn get(&self, key: &T) -> Option<&HashSet<PersonId>> {
+    self.index.get(key)
+  }
+
+  /// Gets a guard giving mutable access to the set associated with the `key` if it exists. If the set is empty when
+  /// the guard is dropped, the key is removed from the index.
+  pub fn get_mut(&mut self, key: &T) -> Option<PruneGuard<'_, T>> {
+    let entry = self.index.occupied_entry(key)?;
+    Some(PruneGuard { entry: Some(entry) })
+  }
+
+  pub fn contains_key(&self, key: &T) -> bool {
+    self.index.contains_key(key)
+  }
+
+  /// Read-only access to the underlying index.
+  pub fn as_index(&self) -> &Index<T> {
+    &self.index
+  }
+}
+
+/// Mutable access to one set of an `AutoPruneIndex`, removing the set's key on drop if the set is empty.
+pub struct PruneGuard<'a, T> {
+  // Always `Some` until the guard is dropped.
+  entry: Option<OccupiedEntry<'a, (T, HashSet<PersonId>)>>,
+}
+
+impl<T> Deref for PruneGuard<'_, T> {
+  type Target = HashSet<PersonId>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.entry.as_ref().unwrap().get().1
+  }
+}
+
+impl<T> DerefMut for PruneGuard<'_, T> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.entry.as_mut().unwrap().get_mut().1
+  }
+}
+
+impl<T> Drop for PruneGuard<'_, T> {
+  fn drop(&mut self) {
+    if let Some(entry) = self.entry.take()
+        && entry.get().1.is_empty()
+    {
+      entry.remove();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn emptied_set_is_pruned() {
+    let mut index: AutoPruneIndex<&'static str> = AutoPruneIndex::new();
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+
+    {
+      let mut set = index.get_mut(&"infected").unwrap();
+      set.remove(&1);
+      set.remove(&2);
+      assert!(set.is_empty());
+    }
+
+    assert!(!index.contains_key(&"infected"));
+    assert!(index.get(&"infected").is_none());
+  }
+
+  #[test]
+  fn non_empty_set_is_kept() {
+    let mut index: AutoPruneIndex<&'static str> = AutoPruneIndex::new();
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+
+    index.get_mut(&"infected").unwrap().remove(&1);
+
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([2])));
+    assert!(index.get_mut(&"recovered").is_none());
+  }
+}
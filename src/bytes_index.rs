@@ -0,0 +1,101 @@
+/*!
+
+An index keyed by serialized values, the "type-erased via serialization" index described in `typed_index`.
+
+The public API takes keys as `&[u8]`, hashed with `hash128::hash_bytes_128`. Each set is stored with the original
+bytes of its key, so equality compares the bytes exactly rather than trusting the hash, and keys can be recovered for
+iteration and display. A typed layer, if any, serializes keys before calling in and deserializes them coming out.
+
+*/
+
+use std::collections::HashSet;
+use hashbrown::HashTable;
+use crate::hash128::hash_bytes_128;
+use crate::{EntityId, HashValueType};
+
+type PersonId = EntityId;
+
+type Entry = (HashValueType, Box<[u8]>, HashSet<PersonId>);
+
+/// An index keyed by byte strings.
+#[derive(Debug, Default, Clone)]
+pub struct BytesIndex {
+  lookup: HashTable<Entry>,
+}
+
+impl BytesIndex {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set.
+  #[must_use]
+  pub fn insert_entity(&mut self, key: &[u8], entity_id: PersonId) -> bool {
+    let hash = hash_bytes_128(key);
+
+    // The stored hash is reused when entries are moved, so the bytes are never rehashed.
+    let hasher = |(stored_hash, _, _): &Entry| *stored_hash as u64;
+    let equality = |(stored_hash, stored_key, _): &Entry| *stored_hash == hash && **stored_key == *key;
+    let (_, _, set) = self.lookup
+        .entry(hash as u64, equality, hasher)
+        .or_insert_with(|| (hash, key.into(), HashSet::new()))
+        .into_mut();
+    set.insert(entity_id)
+  }
+
+  /// Gets the set associated with `key` if it exists.
+  pub fn get(&self, key: &[u8]) -> Option<&HashSet<PersonId>> {
+    let hash = hash_bytes_128(key);
+    let equality = |(stored_hash, stored_key, _): &Entry| *stored_hash == hash && **stored_key == *key;
+    self.lookup.find(hash as u64, equality).map(|(_, _, set)| set)
+  }
+
+  pub fn contains_key(&self, key: &[u8]) -> bool {
+    self.get(key).is_some()
+  }
+
+  /// Iterates over the `(key, set)` pairs in unspecified order.
+  pub fn iter(&self) -> impl Iterator<Item = (&[u8], &HashSet<PersonId>)> {
+    self.lookup.iter().map(|(_, key, set)| (&**key, set))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bytes_index_round_trips_keys() {
+    let mut index = BytesIndex::new();
+    assert!(index.insert_entity(b"infected", 1));
+    assert!(!index.insert_entity(b"infected", 1));
+    let owned: Vec<u8> = b"infected".to_vec();
+    assert!(index.insert_entity(&owned, 2));
+    assert!(index.insert_entity(b"", 3));
+
+    assert_eq!(index.get(b"infected"), Some(&HashSet::from([1, 2])));
+    assert_eq!(index.get(b""), Some(&HashSet::from([3])));
+    assert!(!index.contains_key(b"recovered"));
+
+    let mut keys: Vec<&[u8]> = index.iter().map(|(key, _)| key).collect();
+    keys.sort_unstable();
+    assert_eq!(keys, vec![&b""[..], &b"infected"[..]]);
+  }
+
+  #[test]
+  fn equal_hashes_with_different_bytes_are_distinct_keys() {
+    let mut index = BytesIndex::new();
+    let _ = index.insert_entity(b"infected", 1);
+
+    // Forge an entry whose stored hash is that of "recovered" but whose bytes differ, as a hash collision would.
+    let hash = hash_bytes_128(b"recovered");
+    let hasher = |(stored_hash, _, _): &Entry| *stored_hash as u64;
+    index.lookup.insert_unique(hash as u64, (hash, b"colliding".to_vec().into(), HashSet::from([9])), hasher);
+
+    assert_eq!(index.get(b"recovered"), None);
+    let _ = index.insert_entity(b"recovered", 2);
+    assert_eq!(index.get(b"recovered"), Some(&HashSet::from([2])));
+    assert_eq!(index.lookup.len(), 3);
+  }
+}
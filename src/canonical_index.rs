@@ -0,0 +1,87 @@
+/*!
+
+A typed index that canonicalizes every key before it is hashed, for keys whose `Hash`/`Eq` impls are finer than the
+equality the model wants, e.g. case-insensitive strings or rounded floats.
+
+Create one with `Index::with_canonicalizer`. Only canonical keys are ever stored, so every access path has to go
+through the canonicalizer to be consistent. That is why `CanonicalIndex<T>` is a separate type rather than an option
+on `Index<T>`: it exposes only methods that canonicalize, and `as_index` only gives read access to the canonical keys.
+
+*/
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+use crate::typed_index::Index;
+use crate::EntityId;
+
+type PersonId = EntityId;
+
+type Canonicalizer<T> = Box<dyn Fn(&T) -> T>;
+
+/// A typed index whose keys are canonicalized on every insertion and lookup.
+pub struct CanonicalIndex<T: Hash + Eq + Clone + Any> {
+  index: Index<T>,
+  canonicalizer: Canonicalizer<T>,
+}
+
+impl<T: Hash + Eq + Clone + Any> CanonicalIndex<T> {
+  pub(crate) fn new(canonicalizer: Canonicalizer<T>) -> Self {
+    Self {
+      index: Index::new(),
+      canonicalizer,
+    }
+  }
+
+  /// The canonical form of `key`, which is what the index stores.
+  pub fn canonicalize(&self, key: &T) -> T {
+    (self.canonicalizer)(key)
+  }
+
+  /// Inserts an entity into the set associated with the canonical form of `key`, creating a new set if one does not
+  /// yet exist. Returns a `bool` according to whether the `entity_id` already existed in the set.
+  #[must_use]
+  pub fn insert_entity(&mut self, key: &T, entity_id: PersonId) -> bool {
+    let key = self.canonicalize(key);
+    self.index.insert_entity(&key, entity_id)
+  }
+
+  /// Gets the set associated with the canonical form of `key` if it exists.
+  pub fn get(&self, key: &T) -> Option<&HashSet<PersonId>> {
+    self.index.get(&self.canonicalize(key))
+  }
+
+  /// Gets a mutable reference to the set associated with the canonical form of `key` if it exists.
+  pub fn get_mut(&mut self, key: &T) -> Option<&mut HashSet<PersonId>> {
+    let key = self.canonicalize(key);
+    self.index.get_mut(&key)
+  }
+
+  pub fn contains_key(&self, key: &T) -> bool {
+    self.index.contains_key(&self.canonicalize(key))
+  }
+
+  /// The underlying index, whose keys are all canonical. Queries on it must pass canonical keys.
+  pub fn as_index(&self) -> &Index<T> {
+    &self.index
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn canonical_index_is_case_insensitive() {
+    let mut index = Index::with_canonicalizer(|s: &String| s.to_lowercase());
+
+    assert!(index.insert_entity(&"Foo".to_string(), 1));
+    assert!(!index.insert_entity(&"FOO".to_string(), 1));
+    assert!(index.insert_entity(&"foo".to_string(), 2));
+
+    assert_eq!(index.get(&"foo".to_string()), Some(&HashSet::from([1, 2])));
+    assert!(index.contains_key(&"fOo".to_string()));
+    assert!(index.as_index().contains_key(&"foo".to_string()));
+    assert!(!index.as_index().contains_key(&"Foo".to_string()));
+  }
+}
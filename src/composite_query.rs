@@ -0,0 +1,89 @@
+/*!
+
+A builder for AND queries across several typed indexes, which may each be keyed by a different type.
+
+Each clause's bucket is looked up when the clause is added, so the builder only holds `&HashSet<PersonId>`s and never
+needs to name the indexes' key types. The result is the intersection of all of the buckets.
+
+*/
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+use crate::typed_index::Index;
+use crate::EntityId;
+
+type PersonId = EntityId;
+
+/// An AND query over buckets of any number of `Index<_>`s.
+#[derive(Debug, Default, Clone)]
+pub struct CompositeQuery<'a> {
+  buckets: Vec<&'a HashSet<PersonId>>,
+  /// Set once any clause's key is absent from its index, which makes the result empty.
+  has_absent_key: bool,
+}
+
+impl<'a> CompositeQuery<'a> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Requires the entities in the result to be in the bucket of `key` in `index`.
+  pub fn and<T: Hash + Eq + Clone + Any>(mut self, index: &'a Index<T>, key: &T) -> Self {
+    match index.get(key) {
+      Some(set) => self.buckets.push(set),
+      None => self.has_absent_key = true,
+    }
+    self
+  }
+
+  /// The entities in every clause's bucket. A query with no clauses matches no entities.
+  ///
+  /// The smallest bucket is copied and then filtered against the others, so the cost is linear in its size times the
+  /// number of clauses.
+  pub fn execute(&self) -> HashSet<PersonId> {
+    if self.has_absent_key {
+      return HashSet::new();
+    }
+    let Some((smallest_position, smallest)) = self.buckets.iter().enumerate().min_by_key(|(_, set)| set.len()) else {
+      return HashSet::new();
+    };
+
+    smallest
+        .iter()
+        .copied()
+        .filter(|entity_id| {
+          self.buckets
+              .iter()
+              .enumerate()
+              .all(|(position, set)| position == smallest_position || set.contains(entity_id))
+        })
+        .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn composite_query_intersects_heterogeneous_indexes() {
+    let mut status: Index<String> = Index::new();
+    let mut age: Index<u8> = Index::new();
+    for (id, s, a) in [(1, "infected", 30), (2, "infected", 40), (3, "recovered", 30), (4, "infected", 30)] {
+      let _ = status.insert_entity(&s.to_string(), id);
+      let _ = age.insert_entity(&a, id);
+    }
+
+    let infected = "infected".to_string();
+    let result = CompositeQuery::new().and(&status, &infected).and(&age, &30).execute();
+    assert_eq!(result, HashSet::from([1, 4]));
+
+    // A single clause returns its whole bucket.
+    assert_eq!(CompositeQuery::new().and(&age, &40).execute(), HashSet::from([2]));
+
+    // An absent key or an empty query matches nothing.
+    assert!(CompositeQuery::new().and(&status, &infected).and(&age, &99).execute().is_empty());
+    assert!(CompositeQuery::new().execute().is_empty());
+  }
+}
@@ -0,0 +1,90 @@
+/*!
+
+An index for properties with a small, known value domain, e.g. a `bool` or a fieldless enum with a few variants.
+
+`DenseIndex<N>` is keyed by a discriminant in `0..N` and stores one set per discriminant in an array, so access is a
+bounds-checked array index with no hashing. A fieldless enum converts to its discriminant with `variant as usize`.
+
+*/
+
+use std::collections::HashSet;
+use crate::EntityId;
+
+type PersonId = EntityId;
+
+/// An index with one set for each discriminant in `0..N`.
+#[derive(Debug, Clone)]
+pub struct DenseIndex<const N: usize> {
+  sets: [HashSet<PersonId>; N],
+}
+
+impl<const N: usize> Default for DenseIndex<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<const N: usize> DenseIndex<N> {
+  pub fn new() -> Self {
+    Self {
+      sets: std::array::from_fn(|_| HashSet::new()),
+    }
+  }
+
+  /// Inserts an entity into the set of `variant`, returning whether it was newly added, or `None` without inserting
+  /// if `variant` is not less than `N`.
+  #[must_use]
+  pub fn insert_entity(&mut self, variant: usize, entity_id: PersonId) -> Option<bool> {
+    let set = self.sets.get_mut(variant)?;
+    Some(set.insert(entity_id))
+  }
+
+  /// Gets the set of `variant`, which may be empty, or `None` if `variant` is not less than `N`.
+  pub fn get(&self, variant: usize) -> Option<&HashSet<PersonId>> {
+    self.sets.get(variant)
+  }
+
+  /// Gets a mutable reference to the set of `variant`, or `None` if `variant` is not less than `N`.
+  pub fn get_mut(&mut self, variant: usize) -> Option<&mut HashSet<PersonId>> {
+    self.sets.get_mut(variant)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Clone, Copy)]
+  enum Status {
+    Susceptible,
+    Infected,
+    Recovered,
+  }
+
+  #[test]
+  fn dense_index_in_range() {
+    let mut index: DenseIndex<3> = DenseIndex::new();
+    assert_eq!(index.insert_entity(Status::Infected as usize, 1), Some(true));
+    assert_eq!(index.insert_entity(Status::Infected as usize, 1), Some(false));
+    assert_eq!(index.insert_entity(Status::Recovered as usize, 2), Some(true));
+
+    assert_eq!(index.get(Status::Infected as usize), Some(&HashSet::from([1])));
+    assert_eq!(index.get(Status::Susceptible as usize), Some(&HashSet::new()));
+  }
+
+  #[test]
+  fn dense_index_out_of_range_get() {
+    let mut index: DenseIndex<2> = DenseIndex::new();
+    assert_eq!(index.get(2), None);
+    assert_eq!(index.get_mut(5), None);
+  }
+
+  #[test]
+  fn dense_index_out_of_range_insert() {
+    let mut index: DenseIndex<2> = DenseIndex::new();
+    assert_eq!(index.insert_entity(2, 1), None);
+    assert_eq!(index.insert_entity(usize::MAX, 1), None);
+    // Nothing was inserted anywhere.
+    assert!((0..2).all(|variant| index.get(variant).unwrap().is_empty()));
+  }
+}
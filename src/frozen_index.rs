@@ -0,0 +1,103 @@
+/*!
+
+An immutable typed index packed for lookups, built once a population is loaded and will not change.
+
+`FrozenIndex<T>` stores its buckets in a single boxed slice sorted by the key's 128-bit hash, so there are no empty
+slots or tombstones, and a lookup is a binary search over the hashes. It has no mutation methods; `thaw` turns it back
+into an `Index<T>`.
+
+*/
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+use crate::hash128::one_shot_128_seeded;
+use crate::typed_index::Index;
+use crate::{EntityId, HashValueType};
+
+type PersonId = EntityId;
+
+/// An immutable typed index. Create one with `Index::freeze`.
+#[derive(Debug, Clone)]
+pub struct FrozenIndex<T: Hash + Eq + Clone + Any> {
+  /// Sorted by the stored hash, which is the `one_shot_128_seeded` hash of the key.
+  entries: Box<[(HashValueType, T, HashSet<PersonId>)]>,
+  /// The seed of the index this was frozen from, which `thaw` restores.
+  seed: u64,
+}
+
+impl<T: Hash + Eq + Clone + Any> FrozenIndex<T> {
+  pub(crate) fn from_entries<I: IntoIterator<Item = (T, HashSet<PersonId>)>>(entries: I, seed: u64) -> Self {
+    let mut entries: Vec<_> = entries
+        .into_iter()
+        .map(|(key, set)| (one_shot_128_seeded(&key, seed), key, set))
+        .collect();
+    entries.sort_unstable_by_key(|(hash, _, _)| *hash);
+    Self {
+      entries: entries.into_boxed_slice(),
+      seed,
+    }
+  }
+
+  /// Converts back into a mutable index with the seed it was frozen with.
+  pub fn thaw(self) -> Index<T> {
+    let mut index = Index::with_seed(self.seed);
+    for (_, key, set) in self.entries {
+      // The keys are unique, so `insert_value` is correct here.
+      index.insert_value(key, set);
+    }
+    index
+  }
+
+  /// Fetching a set only requires the hash.
+  pub fn get_with_hash(&self, hash: HashValueType) -> Option<&HashSet<PersonId>> {
+    self.entries
+        .binary_search_by_key(&hash, |(stored_hash, _, _)| *stored_hash)
+        .ok()
+        .map(|position| &self.entries[position].2)
+  }
+
+  /// Gets the set associated with the `key` if it exists.
+  pub fn get(&self, key: &T) -> Option<&HashSet<PersonId>> {
+    self.get_with_hash(one_shot_128_seeded(key, self.seed))
+  }
+
+  pub fn contains_key(&self, key: &T) -> bool {
+    self.get(key).is_some()
+  }
+
+  /// The number of keys.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::hash128::one_shot_128;
+
+  #[test]
+  fn freeze_and_thaw_preserve_contents() {
+    let mut index: Index<u32> = Index::new();
+    for entity_id in 0..100 {
+      let _ = index.insert_entity(&((entity_id % 7) as u32), entity_id);
+    }
+    let fingerprint = index.fingerprint();
+    let expected = index.get(&3).cloned();
+
+    let frozen = index.freeze();
+    assert_eq!(frozen.len(), 7);
+    assert_eq!(frozen.get(&3).cloned(), expected);
+    assert!(frozen.contains_key(&6));
+    assert!(!frozen.contains_key(&7));
+    assert_eq!(frozen.get_with_hash(one_shot_128(&3u32)).cloned(), expected);
+
+    let thawed = frozen.thaw();
+    assert_eq!(thawed.fingerprint(), fingerprint);
+  }
+}
@@ -1,5 +1,7 @@
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use twox_hash::XxHash3_128;
+use crate::{EntityId, HashValueType};
 
 pub struct Xxh3Hasher128(XxHash3_128);
 
@@ -23,19 +25,76 @@ impl Hasher for Xxh3Hasher128 {
 }
 
 impl Xxh3Hasher128 {
+  /// A hasher whose digests are seeded with `seed`, e.g. to make hashes differ from those of another run. Seed 0 gives
+  /// the same digests as `default()`.
+  pub fn with_seed(seed: u64) -> Self {
+    Self(XxHash3_128::with_seed(seed))
+  }
+
   pub fn finish_u128(self) -> u128 {
     // consume the state to produce the 128-bit digest
     self.0.finish_128()
   }
+
+  /// The 128-bit digest of the bytes written so far, without consuming the hasher, so that more bytes can still be
+  /// written. This is the 128-bit counterpart of `finish`.
+  pub fn digest_u128(&self) -> u128 {
+    self.0.finish_128()
+  }
+}
+
+/// A `Hasher` that passes a single already-uniform `u128` through unchanged, for keys that are themselves hashes (e.g.
+/// content hashes computed elsewhere), where hashing again with XXH3 is pure overhead. It only supports `u128` keys:
+/// writing anything other than one `u128` panics.
+///
+/// This is only sound when the keys are genuinely uniformly distributed; structured keys would all land in a few
+/// buckets. See also `hash_key_index::HashKeyIndex`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IdentityHash128(Option<u128>);
+
+impl Hasher for IdentityHash128 {
+  fn write(&mut self, _bytes: &[u8]) {
+    panic!("`IdentityHash128` only supports a single `u128`");
+  }
+
+  fn write_u128(&mut self, value: u128) {
+    assert!(self.0.is_none(), "`IdentityHash128` only supports a single `u128`");
+    self.0 = Some(value);
+  }
+
+  /// The low 64 bits of the key, i.e. the bucket selector the indexes would use for it.
+  fn finish(&self) -> u64 {
+    self.finish_u128() as u64
+  }
+}
+
+impl IdentityHash128 {
+  /// The key that was written, or 0 if none was.
+  pub fn finish_u128(&self) -> u128 {
+    self.0.unwrap_or_default()
+  }
 }
 
 // Helper for any T: Hash
-pub fn one_shot_128<T: Hash>(value: &T) -> u128 {
+pub fn one_shot_128<T: Hash>(value: &T) -> HashValueType {
   let mut h = Xxh3Hasher128::default();
   value.hash(&mut h);
   h.finish_u128()
 }
 
+/// Like `one_shot_128`, but seeded with `seed`. Seed 0 gives the same hash as `one_shot_128`.
+pub fn one_shot_128_seeded<T: Hash>(value: &T, seed: u64) -> HashValueType {
+  let mut h = Xxh3Hasher128::with_seed(seed);
+  value.hash(&mut h);
+  h.finish_u128()
+}
+
+/// The 128-bit XXH3 hash of the raw bytes, e.g. of a serialized key. Unlike `one_shot_128(&bytes)`, this does not
+/// hash a length prefix, so it is the plain XXH3-128 digest of `bytes`.
+pub fn hash_bytes_128(bytes: &[u8]) -> HashValueType {
+  XxHash3_128::oneshot(bytes)
+}
+
 // Helper for any T: Hash
 pub fn one_shot_64<T: Hash>(value: &T) -> u64 {
   let mut h = Xxh3Hasher128::default();
@@ -43,6 +102,81 @@ pub fn one_shot_64<T: Hash>(value: &T) -> u64 {
   h.finish()
 }
 
+/// Hashes the value `value` refers to; the same as `one_shot_128`, spelled out for call sites where that matters.
+///
+/// References and the standard smart pointers (`&T`, `&mut T`, `Box<T>`, `Rc<T>`, `Arc<T>`, and `Cow<'_, T>`)
+/// forward `Hash` to their pointee, so a boxed or reference-counted key hashes the same as the value it holds, and two
+/// distinct allocations of equal values collide. Use `hash_by_ptr` to hash by identity instead.
+pub fn hash_by_value<T: Hash + ?Sized>(value: &T) -> HashValueType {
+  one_shot_128(&value)
+}
+
+/// Hashes the address `ptr` points to rather than the value there, for keying by object identity, e.g. with
+/// `Rc::as_ptr`. Only the address is hashed, not the metadata of a wide pointer. The hash is only meaningful while the
+/// allocation is alive, since addresses are reused after it is freed.
+pub fn hash_by_ptr<T: ?Sized>(ptr: *const T) -> HashValueType {
+  one_shot_128(&ptr.cast::<()>())
+}
+
+/// Counts how many of `keys` share their 64-bit bucket selector (`hash as u64`) with a different key. The indexes
+/// compare full 128-bit hashes, so such keys are still told apart, but the assumption that selectors rarely collide is
+/// what keeps lookups fast; this checks it against a real key distribution. Equal keys count as one key.
+pub fn count_selector_collisions<T: Hash, I: IntoIterator<Item = T>>(keys: I) -> usize {
+  count_selector_collisions_in_hashes(keys.into_iter().map(|key| one_shot_128(&key)))
+}
+
+fn count_selector_collisions_in_hashes<I: IntoIterator<Item = HashValueType>>(hashes: I) -> usize {
+  let mut hashes_by_selector: HashMap<u64, HashSet<HashValueType>> = HashMap::new();
+  for hash in hashes {
+    hashes_by_selector.entry(hash as u64).or_default().insert(hash);
+  }
+  hashes_by_selector.values().map(|hashes| hashes.len()).filter(|&count| count > 1).sum()
+}
+
+/// The fixed input hashed by `algorithm_fingerprint`.
+const ALGORITHM_FINGERPRINT_INPUT: &[u8] = b"ixa-indexing-v1";
+
+/// The `one_shot_128` hash of a fixed, known input. Type-erased indexes persist raw hashes, so a change in the hashing
+/// algorithm (e.g. from a dependency bump) would silently invalidate stored data. A loader can compare this value with
+/// the one recorded when a checkpoint was written to detect that.
+pub fn algorithm_fingerprint() -> HashValueType {
+  one_shot_128(&ALGORITHM_FINGERPRINT_INPUT)
+}
+
+/// Digests an index's contents from its `(key hash, set)` pairs. Each pair is hashed with its members sorted, and the
+/// per-pair digests are combined with XOR, so the result depends on neither insertion order nor iteration order.
+pub(crate) fn index_fingerprint<'a, I>(buckets: I) -> HashValueType
+  where I: IntoIterator<Item = (HashValueType, &'a HashSet<EntityId>)>
+{
+  buckets
+      .into_iter()
+      .map(|(hash, set)| {
+        let mut members: Vec<EntityId> = set.iter().copied().collect();
+        members.sort_unstable();
+        one_shot_128(&(hash, members))
+      })
+      .fold(0, |fingerprint, bucket| fingerprint ^ bucket)
+}
+
+/// An object-safe stand-in for `Hash`, which cannot be made into a trait object because `Hash::hash` is generic over
+/// the hasher. Every `T: Hash` implements it, so heterogeneous keys can be held as `&dyn DynHash`.
+pub trait DynHash {
+  fn dyn_hash(&self, state: &mut dyn Hasher);
+}
+
+impl<T: Hash + ?Sized> DynHash for T {
+  fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+    self.hash(&mut state);
+  }
+}
+
+// Helper for trait objects. Produces the same hash as `one_shot_128` on the underlying concrete value.
+pub fn one_shot_128_dyn(value: &dyn DynHash) -> HashValueType {
+  let mut h = Xxh3Hasher128::default();
+  value.dyn_hash(&mut h);
+  h.finish_u128()
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -64,4 +198,86 @@ mod tests {
     let h2 = one_shot_128(&S { x: 1, y: "a".into() });
     assert_eq!(h1, h2);
   }
+
+  #[test]
+  fn seeded_hashes() {
+    assert_eq!(one_shot_128_seeded(&"hello", 0), one_shot_128(&"hello"));
+    assert_eq!(one_shot_128_seeded(&"hello", 7), one_shot_128_seeded(&"hello", 7));
+    assert_ne!(one_shot_128_seeded(&"hello", 7), one_shot_128(&"hello"));
+    assert_ne!(one_shot_128_seeded(&"hello", 7), one_shot_128_seeded(&"hello", 8));
+  }
+
+  #[test]
+  fn digest_u128_does_not_consume() {
+    let mut h = Xxh3Hasher128::default();
+    1u32.hash(&mut h);
+    let intermediate = h.digest_u128();
+    assert_eq!(intermediate, one_shot_128(&1u32));
+
+    "hello".hash(&mut h);
+    let digest = h.digest_u128();
+    assert_ne!(digest, intermediate);
+    assert_eq!(digest, h.finish_u128());
+  }
+
+  #[test]
+  fn dyn_hash_matches_monomorphic() {
+    #[derive(Hash)]
+    struct S { x: u32, y: String }
+    let s = S { x: 1, y: "a".into() };
+
+    let keys: [&dyn DynHash; 2] = [&"hello", &s];
+    assert_eq!(one_shot_128_dyn(keys[0]), one_shot_128(&"hello"));
+    assert_eq!(one_shot_128_dyn(keys[1]), one_shot_128(&s));
+  }
+
+  #[test]
+  fn hash_by_value_and_by_ptr() {
+    use std::rc::Rc;
+    let a = Rc::new("hello".to_string());
+    let b = Rc::new("hello".to_string());
+
+    // Smart pointers forward to the pointee.
+    assert_eq!(hash_by_value(&a), one_shot_128(&"hello".to_string()));
+    assert_eq!(hash_by_value(&Box::new(1u32)), one_shot_128(&1u32));
+    assert_eq!(hash_by_value(&a), hash_by_value(&b));
+
+    // Equal values in different allocations are distinct by identity.
+    assert_ne!(hash_by_ptr(Rc::as_ptr(&a)), hash_by_ptr(Rc::as_ptr(&b)));
+    assert_eq!(hash_by_ptr(Rc::as_ptr(&a)), hash_by_ptr(Rc::as_ptr(&a.clone())));
+  }
+
+  #[test]
+  fn counts_selector_collisions() {
+    // Real collisions of the 64-bit selector are impractical to construct, so distinct keys should report none.
+    assert_eq!(count_selector_collisions(0..10_000u32), 0);
+    assert_eq!(count_selector_collisions(["a", "a", "b"]), 0);
+
+    // Hashes that differ only in their high 64 bits share a selector.
+    let colliding = [1u128, (1 << 64) | 1, (2 << 64) | 1, 2, 3, (1 << 64) | 3, 2];
+    assert_eq!(count_selector_collisions_in_hashes(colliding), 5);
+  }
+
+  #[test]
+  fn identity_hash_passes_u128_through() {
+    let key: u128 = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+    let mut h = IdentityHash128::default();
+    key.hash(&mut h);
+    assert_eq!(h.finish_u128(), key);
+    assert_eq!(h.finish(), 0xfedc_ba98_7654_3210);
+  }
+
+  #[test]
+  fn hash_bytes_128_is_plain_xxh3() {
+    let mut h = Xxh3Hasher128::default();
+    h.write(b"hello");
+    assert_eq!(hash_bytes_128(b"hello"), h.finish_u128());
+    assert_ne!(hash_bytes_128(b"hello"), hash_bytes_128(b"world"));
+  }
+
+  #[test]
+  fn algorithm_fingerprint_is_pinned() {
+    // If this fails, `one_shot_128` output has changed and previously persisted hashes are no longer valid.
+    assert_eq!(algorithm_fingerprint(), 0xbf49d45e25d60dcb20b75354cd6f1439);
+  }
 }
@@ -0,0 +1,81 @@
+/*!
+
+An index keyed by `u128` values that are already uniform hashes, e.g. content hashes computed elsewhere.
+
+`Index<u128>` would hash every key again with XXH3, on insertion and on every equality check. `HashKeyIndex` uses the
+key itself as the stored hash and takes its bucket selector from `IdentityHash128`, i.e. the key's low 64 bits. This is only valid
+when the keys are genuinely uniformly distributed: sequential or otherwise structured keys would crowd into a few
+buckets and degrade lookups.
+
+*/
+
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use hashbrown::HashTable;
+use crate::hash128::IdentityHash128;
+use crate::{EntityId, HashValueType};
+
+type PersonId = EntityId;
+
+/// An index whose keys are used as their own hashes.
+#[derive(Debug, Default, Clone)]
+pub struct HashKeyIndex {
+  lookup: HashTable<(HashValueType, HashSet<PersonId>)>,
+}
+
+impl HashKeyIndex {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set.
+  #[must_use]
+  pub fn insert_prehashed(&mut self, key: HashValueType, entity_id: PersonId) -> bool {
+    let hasher = |(stored_key, _stored_set): &_| selector(*stored_key);
+    let (_, set) = self.lookup
+        .entry(selector(key), |(stored_key, _)| *stored_key == key, hasher)
+        .or_insert_with(|| (key, HashSet::new()))
+        .into_mut();
+    set.insert(entity_id)
+  }
+
+  /// Gets the set associated with `key` if it exists.
+  pub fn get_prehashed(&self, key: HashValueType) -> Option<&HashSet<PersonId>> {
+    self.lookup.find(selector(key), |(stored_key, _)| *stored_key == key).map(|(_, set)| set)
+  }
+
+  pub fn contains_key(&self, key: HashValueType) -> bool {
+    self.get_prehashed(key).is_some()
+  }
+}
+
+/// The bucket selector of `key`, passed through `IdentityHash128` rather than hashed again.
+fn selector(key: HashValueType) -> u64 {
+  let mut hasher = IdentityHash128::default();
+  key.hash(&mut hasher);
+  hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::hash128::one_shot_128;
+
+  #[test]
+  fn selector_is_the_key_itself() {
+    let key = one_shot_128(&"some content");
+    let mut index = HashKeyIndex::new();
+    assert!(index.insert_prehashed(key, 1));
+    assert!(!index.insert_prehashed(key, 1));
+    assert!(index.insert_prehashed(key, 2));
+    assert_eq!(index.get_prehashed(key), Some(&HashSet::from([1, 2])));
+
+    // The bucket is found under the key's own low 64 bits, not under the XXH3 hash of the key.
+    assert_eq!(selector(key), key as u64);
+    assert!(index.lookup.find(key as u64, |(stored_key, _)| *stored_key == key).is_some());
+    let rehashed = one_shot_128(&key);
+    assert!(index.lookup.find(rehashed as u64, |(stored_key, _)| *stored_key == rehashed).is_none());
+    assert!(!index.contains_key(rehashed));
+  }
+}
@@ -0,0 +1,188 @@
+/*!
+
+A typed index that grows incrementally to cap the latency of any single insertion.
+
+When a `HashTable` is full, the next insertion rehashes every entry into a larger table at once, which shows up as a
+latency spike in online simulation loops. `IncrementalIndex<T>` instead retires the full table as the "old" table,
+allocates a new one of twice the capacity, and moves `MIGRATION_BATCH` entries from old to new on each insertion.
+Queries consult both tables while a migration is in progress.
+
+Two details make migrating an entry cheap:
+
+- Entries store their 128-bit hash (the cached-hash layout), so moving an entry never rehashes the key.
+- Each table keeps a log of the hashes of the keys inserted into it, which becomes the work queue when the table is
+  retired. This avoids scanning the table for entries left to migrate.
+
+The new table has twice the capacity of the old one and at least one entry is migrated per new key, so the old table
+is always empty before the new table fills up. The new table itself never grows in place.
+
+*/
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+use hashbrown::HashTable;
+use crate::hash128::one_shot_128;
+use crate::{EntityId, HashValueType};
+
+type PersonId = EntityId;
+
+/// How many entries are moved from the old table to the new one per insertion.
+const MIGRATION_BATCH: usize = 2;
+/// The capacity of the first table.
+const MIN_CAPACITY: usize = 16;
+
+type Entry<T> = (HashValueType, T, HashSet<PersonId>);
+
+/// A typed index that migrates entries to a larger table a few at a time instead of rehashing all at once.
+#[derive(Debug)]
+pub struct IncrementalIndex<T: Hash + Eq + Clone + Any> {
+  current: HashTable<Entry<T>>,
+  /// Hashes of the keys in `current`.
+  current_hashes: Vec<HashValueType>,
+  /// The retired table, empty unless a migration is in progress.
+  old: HashTable<Entry<T>>,
+  /// Hashes of the keys in `old` that have not been migrated yet.
+  pending: Vec<HashValueType>,
+}
+
+impl<T: Hash + Eq + Clone + Any> Default for IncrementalIndex<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T: Hash + Eq + Clone + Any> IncrementalIndex<T> {
+  pub fn new() -> Self {
+    Self {
+      current: HashTable::new(),
+      current_hashes: Vec::new(),
+      old: HashTable::new(),
+      pending: Vec::new(),
+    }
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set.
+  #[must_use]
+  pub fn insert_entity(&mut self, key: &T, entity_id: PersonId) -> bool {
+    self.migrate(MIGRATION_BATCH);
+
+    let hash = one_shot_128(key);
+    // Equality is determined by comparing the full 128-bit hashes.
+    let hash128_equality = |(stored_hash, _, _): &Entry<T>| *stored_hash == hash;
+    if let Some((_, _, set)) = self.current.find_mut(hash as u64, hash128_equality) {
+      return set.insert(entity_id);
+    }
+    if let Some((_, _, set)) = self.old.find_mut(hash as u64, hash128_equality) {
+      return set.insert(entity_id);
+    }
+
+    if self.current.len() == self.current.capacity() {
+      self.start_migration();
+    }
+    self.current.insert_unique(hash as u64, (hash, key.clone(), HashSet::from([entity_id])), stored_hasher);
+    self.current_hashes.push(hash);
+    true
+  }
+
+  /// Gets an immutable reference to the set associated with the `key` if it exists.
+  pub fn get(&self, key: &T) -> Option<&HashSet<PersonId>> {
+    let hash = one_shot_128(key);
+    let hash128_equality = |(stored_hash, _, _): &Entry<T>| *stored_hash == hash;
+    self.current
+        .find(hash as u64, hash128_equality)
+        .or_else(|| self.old.find(hash as u64, hash128_equality))
+        .map(|(_, _, set)| set)
+  }
+
+  pub fn contains_key(&self, key: &T) -> bool {
+    self.get(key).is_some()
+  }
+
+  /// Is a migration from a retired table in progress?
+  pub fn is_migrating(&self) -> bool {
+    !self.pending.is_empty()
+  }
+
+  /// Retires the full current table and replaces it with one of twice the capacity.
+  fn start_migration(&mut self) {
+    // Only one table can be retired at a time. By construction the previous migration has already finished, but if
+    // not, finishing it here is correct, only slower.
+    self.migrate(usize::MAX);
+
+    let capacity = (self.current.capacity() * 2).max(MIN_CAPACITY);
+    self.old = std::mem::replace(&mut self.current, HashTable::with_capacity(capacity));
+    self.pending = std::mem::take(&mut self.current_hashes);
+  }
+
+  /// Moves up to `count` entries from the old table to the current one.
+  fn migrate(&mut self, count: usize) {
+    for _ in 0..count {
+      let Some(hash) = self.pending.pop() else {
+        break;
+      };
+      if let Ok(entry) = self.old.find_entry(hash as u64, |(stored_hash, _, _)| *stored_hash == hash) {
+        let (entry, _) = entry.remove();
+        self.current.insert_unique(hash as u64, entry, stored_hasher);
+        self.current_hashes.push(hash);
+      }
+    }
+    if self.pending.is_empty() && self.old.capacity() > 0 {
+      // Free the retired table's allocation.
+      self.old = HashTable::new();
+    }
+  }
+}
+
+// > `hasher` is called if entries need to be moved or copied to a new table.
+// > This must return the same hash value that each entry was inserted with.
+fn stored_hasher<T>((stored_hash, _, _): &Entry<T>) -> u64 {
+  *stored_hash as u64
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn lookups_see_both_tables_during_migration() {
+    let mut index: IncrementalIndex<u32> = IncrementalIndex::new();
+    let mut saw_migration = false;
+
+    for key in 0..1000u32 {
+      assert!(index.insert_entity(&key, key as PersonId));
+      saw_migration |= index.is_migrating();
+      // Every key inserted so far is visible, whichever table it is in.
+      for earlier in (0..=key).step_by(37) {
+        assert_eq!(index.get(&earlier), Some(&HashSet::from([earlier as PersonId])));
+      }
+    }
+    assert!(saw_migration);
+
+    // Inserting into existing keys works whichever table they are in.
+    for key in 0..1000u32 {
+      assert!(index.insert_entity(&key, 5000));
+      assert!(!index.insert_entity(&key, 5000));
+    }
+    for key in 0..1000u32 {
+      assert_eq!(index.get(&key).unwrap().len(), 2);
+    }
+    assert!(!index.contains_key(&1000));
+  }
+
+  #[test]
+  fn current_table_never_grows_in_place() {
+    let mut index: IncrementalIndex<u32> = IncrementalIndex::new();
+    let mut capacity = 0;
+    for key in 0..5000u32 {
+      let was_migrating = index.is_migrating();
+      let _ = index.insert_entity(&key, 0);
+      if index.current.capacity() != capacity {
+        // The capacity only changes when a new table is started, i.e. when no migration was underway.
+        assert!(!was_migrating);
+        capacity = index.current.capacity();
+      }
+    }
+  }
+}
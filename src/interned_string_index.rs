@@ -0,0 +1,121 @@
+/*!
+
+A string-keyed index that stores each distinct string once, for string properties drawn from a small vocabulary.
+
+The strings live in an arena owned by the index and are identified by a `u32` id; the sets are held in an `Index<u32>`
+keyed by those ids. The table therefore stores a 4-byte key per set instead of a `String`, and a lookup by `&str`
+never allocates. Interned strings are kept for the lifetime of the index, even if their sets are removed.
+
+*/
+
+use std::collections::HashSet;
+use hashbrown::HashTable;
+use crate::hash128::one_shot_128;
+use crate::typed_index::Index;
+use crate::EntityId;
+
+type PersonId = EntityId;
+
+/// An index keyed by strings, which are interned into an arena.
+#[derive(Debug, Default, Clone)]
+pub struct InternedStringIndex {
+  /// The interned strings, indexed by their id.
+  strings: Vec<Box<str>>,
+  /// Resolves strings to their ids. Each entry is hashed by the string it refers to.
+  ids: HashTable<u32>,
+  index: Index<u32>,
+}
+
+impl InternedStringIndex {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The id of `value` if it has been interned.
+  pub fn interned_id(&self, value: &str) -> Option<u32> {
+    let hash = one_shot_128(&value);
+    self.ids.find(hash as u64, |id| *self.strings[*id as usize] == *value).copied()
+  }
+
+  /// Interns `value` if it is not interned yet, and returns its id.
+  pub fn intern(&mut self, value: &str) -> u32 {
+    if let Some(id) = self.interned_id(value) {
+      return id;
+    }
+    let id = u32::try_from(self.strings.len()).expect("too many interned strings");
+    self.strings.push(value.into());
+
+    let strings = &self.strings;
+    let hasher = |id: &u32| one_shot_128(&&*strings[*id as usize]) as u64;
+    self.ids.insert_unique(one_shot_128(&value) as u64, id, hasher);
+    id
+  }
+
+  /// The string with the given id, or `None` if no string has that id.
+  pub fn resolve(&self, id: u32) -> Option<&str> {
+    self.strings.get(id as usize).map(|value| &**value)
+  }
+
+  /// The number of distinct strings interned.
+  pub fn interned_count(&self) -> usize {
+    self.strings.len()
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set.
+  #[must_use]
+  pub fn insert_entity(&mut self, key: &str, entity_id: PersonId) -> bool {
+    let id = self.intern(key);
+    self.index.insert_entity(&id, entity_id)
+  }
+
+  /// Gets the set associated with `key` if it exists.
+  pub fn get(&self, key: &str) -> Option<&HashSet<PersonId>> {
+    self.index.get(&self.interned_id(key)?)
+  }
+
+  pub fn contains_key(&self, key: &str) -> bool {
+    self.get(key).is_some()
+  }
+
+  /// The underlying index, keyed by interned ids.
+  pub fn as_index(&self) -> &Index<u32> {
+    &self.index
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn equal_strings_share_one_interned_entry() {
+    let mut index = InternedStringIndex::new();
+    let infected = String::from("infected");
+
+    let _ = index.insert_entity(&infected, 1);
+    let _ = index.insert_entity("infected", 2);
+    let _ = index.insert_entity("recovered", 3);
+    assert_eq!(index.interned_count(), 2);
+
+    assert_eq!(index.get("infected"), Some(&HashSet::from([1, 2])));
+    assert_eq!(index.get(&infected), Some(&HashSet::from([1, 2])));
+    assert!(index.contains_key("recovered"));
+    assert!(!index.contains_key("exposed"));
+
+    let id = index.interned_id("recovered").unwrap();
+    assert_eq!(index.resolve(id), Some("recovered"));
+    assert_eq!(index.intern("recovered"), id);
+  }
+
+  #[test]
+  fn interning_survives_table_growth() {
+    let mut index = InternedStringIndex::new();
+    for i in 0..100 {
+      let _ = index.insert_entity(&i.to_string(), i);
+    }
+    for i in 0..100 {
+      assert_eq!(index.get(&i.to_string()), Some(&HashSet::from([i])));
+    }
+  }
+}
@@ -16,21 +16,399 @@ Nice to have:
 // #![feature(generic_const_exprs)]
 // #![feature(const_type_id)]
 #![allow(dead_code)]
-use std::any::TypeId;
+use std::any::{Any, TypeId};
 use std::collections::HashMap;
 use crate::typed_index::BxIndex;
 
-mod type_erased_index;
-mod hash128;
-mod typed_index;
+pub mod type_erased_index;
+pub mod hash128;
+pub mod typed_index;
 mod multi_index;
+pub mod array_key_index;
+pub mod sharded_index;
+pub mod auto_prune_index;
+pub mod incremental_index;
+pub mod temporal_index;
+pub mod observable_index;
+pub mod vec_backed_index;
+pub mod composite_query;
+pub mod frozen_index;
+pub mod canonical_index;
+pub mod dense_index;
+pub mod interned_string_index;
+pub mod hash_key_index;
+pub mod monitored_index;
+pub mod bytes_index;
 // mod tuple_sort;
 
 pub type EntityId = u64;
 
+/// The type of the hashes the indexes key their sets by. Hashes computed with `hash128::one_shot_128` have this type,
+/// and every method that takes or returns a hash uses it.
+pub type HashValueType = u128;
+
+#[derive(Default)]
 struct PropertyManager {
   /// Resolves property names to type ids.
   property_names: HashMap<&'static str, TypeId>,
   indexes       : HashMap<TypeId, BxIndex>,
 
 }
+
+/// A copy of every index registered with a `PropertyManager`, along with the names they were registered under.
+struct ManagerSnapshot {
+  property_names: HashMap<&'static str, TypeId>,
+  indexes       : HashMap<TypeId, BxIndex>,
+}
+
+impl PropertyManager {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Registers `index` as the index of the property `P`, which can then be fetched by `name`. If `name` was bound to
+  /// a different property, that property's index is dropped, unless another name still resolves to it.
+  ///
+  /// Indexes are keyed by property, so every name registered for `P` shares one index: registering `P` again, even
+  /// under a new name, replaces the index that the other names of `P` resolve to. The displaced index is returned so
+  /// that its contents are not lost silently.
+  pub fn register_property<P: Any>(&mut self, name: &'static str, index: BxIndex) -> Option<BxIndex> {
+    let type_id = TypeId::of::<P>();
+    if let Some(previous) = self.property_names.insert(name, type_id)
+        && previous != type_id
+    {
+      self.drop_index_if_unnamed(previous);
+    }
+    self.indexes.insert(type_id, index)
+  }
+
+  /// Removes the property registered under `name`, returning whether there was one. Its index is dropped too, unless
+  /// another name still resolves to the same property.
+  pub fn unregister_property(&mut self, name: &str) -> bool {
+    let Some(type_id) = self.property_names.remove(name) else {
+      return false;
+    };
+    self.drop_index_if_unnamed(type_id);
+    true
+  }
+
+  /// Drops the index of the property `type_id` if no name resolves to it any more, so that it cannot linger unreachable.
+  fn drop_index_if_unnamed(&mut self, type_id: TypeId) {
+    if !self.property_names.values().any(|other| *other == type_id) {
+      self.indexes.remove(&type_id);
+    }
+  }
+
+  /// Fetches the index of the property registered under `name`.
+  pub fn get_index(&self, name: &str) -> Option<&BxIndex> {
+    let type_id = self.property_names.get(name)?;
+    self.indexes.get(type_id)
+  }
+
+  /// Fetches the index of the property registered under `name`.
+  pub fn get_index_mut(&mut self, name: &str) -> Option<&mut BxIndex> {
+    let type_id = self.property_names.get(name)?;
+    self.indexes.get_mut(type_id)
+  }
+
+  /// Iterates over the registered indexes together with their property names, in unspecified order. Each index is
+  /// yielded once; if several names resolve to the same index, one of them is used.
+  pub fn iter_indexes(&self) -> impl Iterator<Item = (&'static str, &BxIndex)> {
+    let names = self.names_by_type_id();
+    self.indexes
+        .iter()
+        .filter_map(move |(type_id, index)| Some((*names.get(type_id)?, index)))
+  }
+
+  /// Like `iter_indexes`, but yields mutable references.
+  pub fn iter_indexes_mut(&mut self) -> impl Iterator<Item = (&'static str, &mut BxIndex)> {
+    let names = self.names_by_type_id();
+    self.indexes
+        .iter_mut()
+        .filter_map(move |(type_id, index)| Some((*names.get(type_id)?, index)))
+  }
+
+  /// Copies every registered index and its names, e.g. to branch a scenario and later return to this point with
+  /// `restore_all`.
+  pub fn snapshot_all(&self) -> ManagerSnapshot {
+    ManagerSnapshot {
+      property_names: self.property_names.clone(),
+      indexes       : self.indexes.iter().map(|(type_id, index)| (*type_id, index.box_clone())).collect(),
+    }
+  }
+
+  /// Replaces every registered index and name with those in `snapshot`. Properties registered after the snapshot was
+  /// taken are unregistered.
+  pub fn restore_all(&mut self, snapshot: ManagerSnapshot) {
+    self.property_names = snapshot.property_names;
+    self.indexes = snapshot.indexes;
+  }
+
+  /// A read-only view of the manager, for handing out query access without the ability to register or mutate indexes.
+  pub fn read_only(&self) -> ReadOnlyManager<'_> {
+    ReadOnlyManager { manager: self }
+  }
+
+  /// Inverts `property_names`.
+  fn names_by_type_id(&self) -> HashMap<TypeId, &'static str> {
+    self.property_names.iter().map(|(name, type_id)| (*type_id, *name)).collect()
+  }
+
+  /// Sums `TypeErasedIndex::entity_count` over all registered indexes.
+  ///
+  /// Indexes of properties that partition the population should all report the population size, so comparing their
+  /// counts is a cheap consistency check.
+  pub fn total_entities_across_indexes(&self) -> usize {
+    self.indexes.values().map(|index| index.entity_count()).sum()
+  }
+
+  /// Empties every registered index, e.g. between runs, while keeping room for `capacity_hint` keys in each so that
+  /// the next run does not have to grow them again. The properties stay registered.
+  pub fn reset_all(&mut self, capacity_hint: usize) {
+    for index in self.indexes.values_mut() {
+      index.clear_and_reserve(capacity_hint);
+    }
+  }
+
+  /// Sums `TypeErasedIndex::entity_degree` over all registered indexes: the number of sets across all properties
+  /// that contain `entity_id`.
+  pub fn total_degree(&self, entity_id: EntityId) -> usize {
+    self.indexes.values().map(|index| index.entity_degree(entity_id)).sum()
+  }
+
+  /// Sums `TypeErasedIndex::approx_memory_bytes` over all registered indexes, an estimate of their total heap usage.
+  pub fn approx_memory_bytes(&self) -> usize {
+    self.indexes.values().map(|index| index.approx_memory_bytes()).sum()
+  }
+}
+
+/// Borrows a `PropertyManager` immutably and exposes only its queries. Indexes fetched through it are `&BxIndex`, so
+/// they cannot be mutated either.
+#[derive(Clone, Copy)]
+struct ReadOnlyManager<'a> {
+  manager: &'a PropertyManager,
+}
+
+impl<'a> ReadOnlyManager<'a> {
+  /// Fetches the index of the property registered under `name`.
+  pub fn get_index(&self, name: &str) -> Option<&'a BxIndex> {
+    self.manager.get_index(name)
+  }
+
+  /// Is a property registered under `name`?
+  pub fn contains_property(&self, name: &str) -> bool {
+    self.manager.property_names.contains_key(name)
+  }
+
+  /// Iterates over the registered indexes together with their property names, like `PropertyManager::iter_indexes`.
+  pub fn iter_indexes(&self) -> impl Iterator<Item = (&'static str, &'a BxIndex)> + 'a {
+    self.manager.iter_indexes()
+  }
+
+  pub fn total_entities_across_indexes(&self) -> usize {
+    self.manager.total_entities_across_indexes()
+  }
+
+  pub fn total_degree(&self, entity_id: EntityId) -> usize {
+    self.manager.total_degree(entity_id)
+  }
+
+  pub fn approx_memory_bytes(&self) -> usize {
+    self.manager.approx_memory_bytes()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashSet;
+  use crate::typed_index::{Index, TypeErasedIndex};
+
+  struct Age;
+  struct Region;
+
+  #[test]
+  fn total_entities_across_indexes() {
+    let mut ages: Index<u8> = Index::new();
+    let _ = ages.insert_entity(&30, 1);
+    let _ = ages.insert_entity(&30, 2);
+    let _ = ages.insert_entity(&41, 3);
+
+    let mut regions: Index<&'static str> = Index::new();
+    let _ = regions.insert_entity(&"north", 1);
+    let _ = regions.insert_entity(&"south", 2);
+    let _ = regions.insert_entity(&"south", 3);
+
+    let mut manager = PropertyManager::new();
+    manager.register_property::<Age>("age", Box::new(ages));
+    manager.register_property::<Region>("region", Box::new(regions));
+
+    assert_eq!(manager.get_index("age").unwrap().entity_count(), 3);
+    assert_eq!(manager.get_index("region").unwrap().entity_count(), 3);
+    assert_eq!(manager.total_entities_across_indexes(), 6);
+  }
+
+  #[test]
+  fn total_degree_sums_indexes() {
+    let mut ages: Index<u8> = Index::new();
+    let _ = ages.insert_entity(&30, 1);
+    let mut regions: Index<&'static str> = Index::new();
+    let _ = regions.insert_entity(&"north", 1);
+    let _ = regions.insert_entity(&"commuter_zone", 1);
+    let _ = regions.insert_entity(&"south", 2);
+
+    let mut manager = PropertyManager::new();
+    manager.register_property::<Age>("age", Box::new(ages));
+    manager.register_property::<Region>("region", Box::new(regions));
+    assert_eq!(manager.total_degree(1), 3);
+    assert_eq!(manager.total_degree(2), 1);
+    assert_eq!(manager.total_degree(3), 0);
+  }
+
+  #[test]
+  fn reset_all_empties_every_index() {
+    let mut ages: Index<u8> = Index::new();
+    let _ = ages.insert_entity(&30, 1);
+    let mut regions: Index<&'static str> = Index::new();
+    let _ = regions.insert_entity(&"north", 1);
+
+    let mut manager = PropertyManager::new();
+    manager.register_property::<Age>("age", Box::new(ages));
+    manager.register_property::<Region>("region", Box::new(regions));
+
+    manager.reset_all(64);
+    assert_eq!(manager.total_entities_across_indexes(), 0);
+    assert!(manager.get_index("age").is_some());
+    assert!(manager.get_index("region").unwrap().approx_memory_bytes() > 0);
+  }
+
+  #[test]
+  fn approx_memory_bytes_sums_indexes() {
+    let mut ages: Index<u8> = Index::new();
+    let _ = ages.insert_entity(&30, 1);
+    let mut regions: Index<&'static str> = Index::new();
+    let _ = regions.insert_entity(&"north", 1);
+    let expected = ages.approx_memory_bytes() + regions.approx_memory_bytes();
+
+    let mut manager = PropertyManager::new();
+    manager.register_property::<Age>("age", Box::new(ages));
+    manager.register_property::<Region>("region", Box::new(regions));
+    assert_eq!(manager.approx_memory_bytes(), expected);
+  }
+
+  #[test]
+  fn snapshot_all_and_restore_all() {
+    let mut ages: Index<u8> = Index::new();
+    let _ = ages.insert_entity(&30, 1);
+    let mut regions: Index<&'static str> = Index::new();
+    let _ = regions.insert_entity(&"north", 1);
+
+    let mut manager = PropertyManager::new();
+    manager.register_property::<Age>("age", Box::new(ages));
+    manager.register_property::<Region>("region", Box::new(regions));
+    let snapshot = manager.snapshot_all();
+
+    let thirty = crate::hash128::one_shot_128(&30u8);
+    manager.get_index_mut("age").unwrap().get_with_hash_mut(thirty).unwrap().insert(2);
+    manager.unregister_property("region");
+    assert_eq!(manager.get_index("age").unwrap().entity_count(), 2);
+
+    manager.restore_all(snapshot);
+    assert_eq!(manager.get_index("age").unwrap().get_with_hash(thirty), Some(&HashSet::from([1])));
+    assert_eq!(manager.get_index("region").unwrap().entity_count(), 1);
+  }
+
+  #[test]
+  fn read_only_view_answers_queries() {
+    let mut ages: Index<u8> = Index::new();
+    let _ = ages.insert_entity(&30, 1);
+    let _ = ages.insert_entity(&30, 2);
+
+    let mut manager = PropertyManager::new();
+    manager.register_property::<Age>("age", Box::new(ages));
+
+    // The view only has `&self` methods and hands out `&BxIndex`, so nothing reachable through it can mutate.
+    let view = manager.read_only();
+    let thirty = crate::hash128::one_shot_128(&30u8);
+    assert_eq!(view.get_index("age").unwrap().get_with_hash(thirty), Some(&HashSet::from([1, 2])));
+    assert!(view.contains_property("age"));
+    assert!(!view.contains_property("region"));
+    assert_eq!(view.iter_indexes().count(), 1);
+    assert_eq!(view.total_entities_across_indexes(), 2);
+    assert_eq!(view.total_degree(1), 1);
+  }
+
+  #[test]
+  fn unregister_property_removes_name_and_index() {
+    let mut manager = PropertyManager::new();
+    manager.register_property::<Age>("age", Box::new(Index::<u8>::new()));
+
+    assert!(manager.unregister_property("age"));
+    assert!(manager.get_index("age").is_none());
+    assert!(!manager.indexes.contains_key(&TypeId::of::<Age>()));
+    assert!(!manager.unregister_property("age"));
+  }
+
+  #[test]
+  fn unregister_property_keeps_shared_index() {
+    let mut manager = PropertyManager::new();
+    manager.register_property::<Age>("age", Box::new(Index::<u8>::new()));
+    manager.register_property::<Age>("years", Box::new(Index::<u8>::new()));
+
+    assert!(manager.unregister_property("age"));
+    assert!(manager.get_index("age").is_none());
+    assert!(manager.get_index("years").is_some());
+
+    assert!(manager.unregister_property("years"));
+    assert!(manager.indexes.is_empty());
+  }
+
+  #[test]
+  fn register_property_rebinding_a_name_drops_orphaned_index() {
+    let mut ages: Index<u8> = Index::new();
+    let _ = ages.insert_entity(&30, 1);
+    let mut manager = PropertyManager::new();
+    assert!(manager.register_property::<Age>("age", Box::new(ages)).is_none());
+
+    // Registering `Age` under a second name replaces the index both names share, and hands back the old one.
+    let displaced = manager.register_property::<Age>("years", Box::new(Index::<u8>::new())).unwrap();
+    assert_eq!(displaced.entity_count(), 1);
+    assert_eq!(manager.get_index("age").unwrap().entity_count(), 0);
+
+    // `years` still resolves to `Age`, so its index survives the first rebinding.
+    manager.register_property::<Region>("age", Box::new(Index::<&'static str>::new()));
+    assert!(manager.indexes.contains_key(&TypeId::of::<Age>()));
+    assert!(manager.get_index("years").is_some());
+
+    // Now nothing resolves to `Age`, so its index is dropped instead of lingering unreachable.
+    manager.register_property::<Region>("years", Box::new(Index::<&'static str>::new()));
+    assert!(!manager.indexes.contains_key(&TypeId::of::<Age>()));
+    assert_eq!(manager.indexes.len(), 1);
+    assert_eq!(manager.total_entities_across_indexes(), 0);
+  }
+
+  #[test]
+  fn iter_indexes_pairs_names_with_indexes() {
+    let mut ages: Index<u8> = Index::new();
+    let _ = ages.insert_entity(&30, 1);
+    let mut regions: Index<&'static str> = Index::new();
+    let _ = regions.insert_entity(&"north", 1);
+    let _ = regions.insert_entity(&"south", 2);
+
+    let mut manager = PropertyManager::new();
+    manager.register_property::<Age>("age", Box::new(ages));
+    manager.register_property::<Region>("region", Box::new(regions));
+
+    let mut counts: Vec<_> = manager.iter_indexes().map(|(name, index)| (name, index.entity_count())).collect();
+    counts.sort();
+    assert_eq!(counts, vec![("age", 1), ("region", 2)]);
+
+    let north = crate::hash128::one_shot_128(&"north");
+    for (name, index) in manager.iter_indexes_mut() {
+      if name == "region" {
+        index.get_with_hash_mut(north).unwrap().insert(3);
+      }
+    }
+    assert_eq!(manager.get_index("region").unwrap().entity_count(), 3);
+  }
+}
@@ -0,0 +1,122 @@
+/*!
+
+A typed index that reports when its table grows, for monitoring rehashes as latency events, e.g. to tune initial
+capacities.
+
+Growth is detected by comparing the table's capacity before and after each mutation that can add a key, so the base
+`Index<T>` needs no hooks. Only the key table is monitored, not the per-key sets.
+
+*/
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+use crate::typed_index::Index;
+use crate::EntityId;
+
+type PersonId = EntityId;
+
+type GrowCallback = Box<dyn FnMut(usize, usize)>;
+
+/// A typed index that calls a callback with the old and new capacity whenever its table grows.
+pub struct MonitoredIndex<T: Hash + Eq + Clone + Any> {
+  index: Index<T>,
+  on_grow: Option<GrowCallback>,
+}
+
+impl<T: Hash + Eq + Clone + Any> Default for MonitoredIndex<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T: Hash + Eq + Clone + Any> MonitoredIndex<T> {
+  pub fn new() -> Self {
+    Self {
+      index: Index::new(),
+      on_grow: None,
+    }
+  }
+
+  /// Sets the callback called with `(old_capacity, new_capacity)` after the table grows, replacing any previous one.
+  pub fn on_grow(&mut self, callback: impl FnMut(usize, usize) + 'static) {
+    self.on_grow = Some(Box::new(callback));
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set.
+  #[must_use]
+  pub fn insert_entity(&mut self, key: &T, entity_id: PersonId) -> bool {
+    let old_capacity = self.index.capacity();
+    let inserted = self.index.insert_entity(key, entity_id);
+    self.check_growth(old_capacity);
+    inserted
+  }
+
+  /// Reserves capacity for at least `additional` more keys. Calls the callback if the table grows.
+  pub fn reserve(&mut self, additional: usize) {
+    let old_capacity = self.index.capacity();
+    self.index.try_reserve(additional).expect("allocation failed");
+    self.check_growth(old_capacity);
+  }
+
+  /// Gets the set associated with `key` if it exists.
+  pub fn get(&self, key: &T) -> Option<&HashSet<PersonId>> {
+    self.index.get(key)
+  }
+
+  pub fn contains_key(&self, key: &T) -> bool {
+    self.index.contains_key(key)
+  }
+
+  /// The underlying index. Only read access is given, so that every growth is observed.
+  pub fn as_index(&self) -> &Index<T> {
+    &self.index
+  }
+
+  fn check_growth(&mut self, old_capacity: usize) {
+    let new_capacity = self.index.capacity();
+    if new_capacity > old_capacity && let Some(on_grow) = self.on_grow.as_mut() {
+      on_grow(old_capacity, new_capacity);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn on_grow_reports_increasing_capacities() {
+    let growths = Rc::new(RefCell::new(Vec::new()));
+    let mut index: MonitoredIndex<u32> = MonitoredIndex::new();
+    let recorded = growths.clone();
+    index.on_grow(move |old, new| recorded.borrow_mut().push((old, new)));
+
+    for key in 0..100 {
+      let _ = index.insert_entity(&key, key as PersonId);
+    }
+    let growths = growths.borrow();
+    assert!(!growths.is_empty());
+    assert_eq!(growths[0].0, 0);
+    assert!(growths.iter().all(|(old, new)| old < new));
+    assert!(growths.windows(2).all(|pair| pair[0].1 == pair[1].0));
+    assert_eq!(growths.last().unwrap().1, index.as_index().capacity());
+  }
+
+  #[test]
+  fn reserve_reports_growth_once() {
+    let count = Rc::new(RefCell::new(0));
+    let mut index: MonitoredIndex<u32> = MonitoredIndex::new();
+    let recorded = count.clone();
+    index.on_grow(move |_, _| *recorded.borrow_mut() += 1);
+
+    index.reserve(1000);
+    for key in 0..100 {
+      let _ = index.insert_entity(&key, 0);
+    }
+    assert_eq!(*count.borrow(), 1);
+  }
+}
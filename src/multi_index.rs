@@ -17,6 +17,12 @@ fields but different names. But this feels more complicated than the proc macro
 
 */
 
+use std::collections::HashSet;
+use std::hash::Hash;
+use crate::hash128::one_shot_128;
+use crate::type_erased_index;
+use crate::EntityId;
+
 trait Property {
   type Value;
 }
@@ -27,8 +33,13 @@ we can implement `SortableTuple` for the value tuple type.
 
 EDIT: Actually, this doesn't work, because the value tuple type might be `(u8, u8, u8)`, which tells you
 nothing about the order of the properties.
+
+Note that `sorted_tuple_impl` cannot also emit `From` impls between the two tuple types: `From` and tuples are both
+foreign, so the orphan rule forbids `impl From<(A, B)> for (B, A)` even when `A` or `B` is a local type, and an
+already-sorted tuple would collide with the blanket `impl<T> From<T> for T`. Generic code should bound on
+`SortableTuple<Sorted = S>` instead of `Into<S>`.
 */
-trait SortableTuple {
+pub trait SortableTuple {
   type Sorted;
 
   fn to_sorted_tuple(self) -> Self::Sorted;
@@ -41,15 +52,79 @@ If we want to sort by the property names instead of the property value types, we
 the tag tuple, because `Tag` is the unordered value, and `SortByTag<Tag>::SortedTag` is the ordered
 value.
 */
-trait SortByTag<Tag> {
+pub trait SortByTag<Tag> {
   type SortedTag;
   type ReorderedValue;
 
+  /// The permutation applied by `reorder_by_tag`: `PERMUTATION[i]` is the position in the sorted order of the `i`th
+  /// element of the original order. Use it to apply the same reordering to runtime collections, e.g. a `Vec` of
+  /// columns. It has one entry per tag.
+  const PERMUTATION: &'static [usize];
+
   fn reorder_by_tag(self) -> Self::ReorderedValue;
   /// The inverse of `reorder_by_tag`. Note that this is an associated function, not a method.
   fn unreorder_by_tag(sorted: Self::ReorderedValue) -> Self;
 }
 
+/// An index over several properties at once, backed by a `type_erased_index::Index`.
+///
+/// A composite key is given as a value tuple together with its tag tuple. The values are reordered into the
+/// canonical tag order with `SortByTag` before hashing, so the same logical key reaches the same set whichever order
+/// its tags are declared in.
+#[derive(Default, Debug)]
+pub struct MultiIndex {
+  index: type_erased_index::Index,
+}
+
+impl MultiIndex {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Inserts an entity into the set associated with the composite key `values`, whose components are tagged by
+  /// `Tags`, creating a new set if one does not yet exist. Returns a `bool` according to whether the `entity_id`
+  /// already existed in the set.
+  pub fn insert_entity_by_tuple<Tags, Values>(&mut self, values: Values, entity_id: EntityId) -> bool
+    where Values: SortByTag<Tags>,
+          Values::ReorderedValue: Hash
+  {
+    let hash = one_shot_128(&values.reorder_by_tag());
+    match self.index.get_with_hash_mut(hash) {
+      Some(set) => set.insert(entity_id),
+      None => {
+        self.index.insert_with_hash(hash, HashSet::from([entity_id]));
+        true
+      }
+    }
+  }
+
+  /// Gets the set associated with the composite key `values`, whose components are tagged by `Tags`.
+  pub fn get_by_tuple<Tags, Values>(&self, values: Values) -> Option<&HashSet<EntityId>>
+    where Values: SortByTag<Tags>,
+          Values::ReorderedValue: Hash
+  {
+    let hash = one_shot_128(&values.reorder_by_tag());
+    self.index.get_with_hash(hash)
+  }
+}
+
+/// Reorders `items` by `perm`, moving `items[i]` to position `perm[i]`. With a generated `SortByTag::PERMUTATION`,
+/// this puts runtime data that arrives in declared tag order, e.g. a `Vec` of columns, into the canonical sorted order
+/// that `reorder_by_tag` gives the value tuple:
+///
+/// ```ignore
+/// let columns = apply_permutation(<Values as SortByTag<Tags>>::PERMUTATION, columns);
+/// ```
+///
+/// Panics if `perm` is not a permutation of `0..items.len()`.
+pub fn apply_permutation<T>(perm: &[usize], items: Vec<T>) -> Vec<T> {
+  assert_eq!(perm.len(), items.len(), "permutation and items have different lengths");
+  let mut reordered: Vec<Option<T>> = (0..items.len()).map(|_| None).collect();
+  for (item, &position) in items.into_iter().zip(perm) {
+    assert!(reordered[position].replace(item).is_none(), "`perm` is not a permutation");
+  }
+  reordered.into_iter().map(|item| item.expect("`perm` is not a permutation")).collect()
+}
 
 
 #[cfg(test)]
@@ -73,6 +148,17 @@ mod tests {
     println!("original: {:?}, sorted: {:?}", a, sorted);
   }
 
+  #[test]
+  fn test_sorted_tuple_generic_bound() {
+    // The stand-in for an `Into<Sorted>` bound: accepts any tuple whose sorted form is `Sorted`.
+    fn sorted_form<U: SortableTuple<Sorted = (&'static str, f64, usize)>>(unsorted: U) -> (&'static str, f64, usize) {
+      unsorted.to_sorted_tuple()
+    }
+
+    let a = (1usize, 2.0, "a");
+    assert_eq!(sorted_form(a), a.to_sorted_tuple());
+  }
+
 
   struct TagA;
   struct TagB;
@@ -94,21 +180,24 @@ mod tests {
   );
   /*
   // The macro above generates the following:
+  #[automatically_derived]
   impl SortByTag<(TagC, TagA, TagB)> for (u8, &'static str, f64) {
     type SortedTag = (TagA, TagB, TagC);
     type ReorderedValue = (&'static str, f64, u8);
+    const PERMUTATION: &'static [usize] = &[2usize, 0usize, 1usize];
     fn reorder_by_tag(self) -> Self::ReorderedValue {
-      let (t0, t1, t2) = self;
-      (t1, t2, t0)
+      let (__ixa_t0, __ixa_t1, __ixa_t2) = self;
+      (__ixa_t1, __ixa_t2, __ixa_t0)
     }
     fn unreorder_by_tag(sorted: Self::ReorderedValue) -> Self {
-      let (s0, s1, s2) = sorted;
-      (s2, s0, s1)
+      let (__ixa_s0, __ixa_s1, __ixa_s2) = sorted;
+      (__ixa_s2, __ixa_s0, __ixa_s1)
     }
   }
   */
 
   #[test]
+  #[allow(clippy::approx_constant)]
   fn test_sort_by_tag() {
     let values = (123u8, "hi", 3.14);
     let sorted = values.reorder_by_tag();
@@ -125,5 +214,58 @@ mod tests {
     assert_eq!(unsorted, values);
   }
 
+  #[test]
+  fn test_permutation() {
+    let permutation = <(u8, &'static str, f64) as SortByTag<(TagC, TagA, TagB)>>::PERMUTATION;
+    // TagC moves to the end, TagA and TagB move up one place.
+    assert_eq!(permutation, &[2, 0, 1]);
+
+    // Applying the permutation by hand agrees with the tuple reordering.
+    let values = ["c", "a", "b"];
+    let mut reordered = [""; 3];
+    for (i, value) in values.into_iter().enumerate() {
+      reordered[permutation[i]] = value;
+    }
+    assert_eq!(reordered, ["a", "b", "c"]);
+  }
+
+  #[test]
+  fn test_apply_permutation() {
+    let permutation = <(u8, &'static str, f64) as SortByTag<(TagC, TagA, TagB)>>::PERMUTATION;
+    let columns = vec![vec!["c0", "c1"], vec!["a0", "a1"], vec!["b0", "b1"]];
+    assert_eq!(apply_permutation(permutation, columns), vec![vec!["a0", "a1"], vec!["b0", "b1"], vec!["c0", "c1"]]);
+
+    assert_eq!(apply_permutation::<u8>(&[], vec![]), Vec::<u8>::new());
+  }
+
+  #[test]
+  #[should_panic(expected = "not a permutation")]
+  fn test_apply_permutation_rejects_repeats() {
+    apply_permutation(&[0, 0], vec![1, 2]);
+  }
+
+  // Two declared orders of the same three tags, for the same logical key types.
+  sorted_tag_value_impl!(
+    tag_tuple = (TagC, TagA, TagB),
+    value_tuple = (u8, &'static str, u32)
+  );
+  sorted_tag_value_impl!(
+    tag_tuple = (TagB, TagC, TagA),
+    value_tuple = (u32, u8, &'static str)
+  );
+
+  #[test]
+  fn test_multi_index_tag_order_independence() {
+    let mut index = MultiIndex::new();
+    assert!(index.insert_entity_by_tuple::<(TagC, TagA, TagB), _>((7u8, "north", 42u32), 1));
+    assert!(!index.insert_entity_by_tuple::<(TagC, TagA, TagB), _>((7u8, "north", 42u32), 1));
+    // The same logical key in a different tag order reaches the same set.
+    assert!(index.insert_entity_by_tuple::<(TagB, TagC, TagA), _>((42u32, 7u8, "north"), 2));
+
+    let expected = HashSet::from([1, 2]);
+    assert_eq!(index.get_by_tuple::<(TagC, TagA, TagB), _>((7u8, "north", 42u32)), Some(&expected));
+    assert_eq!(index.get_by_tuple::<(TagB, TagC, TagA), _>((42u32, 7u8, "north")), Some(&expected));
+    assert_eq!(index.get_by_tuple::<(TagB, TagC, TagA), _>((42u32, 7u8, "south")), None);
+  }
 
 }
@@ -0,0 +1,169 @@
+/*!
+
+A typed index that notifies observers of every change, for keeping derived state in sync without polling.
+
+The base `Index<T>` stays free of hooks so that its hot paths pay nothing; observability is opt-in by wrapping it in
+`ObservableIndex<T>`. Observers are called synchronously, in registration order, with an `IndexEvent` describing each
+change. A mutation that creates or removes a key emits the key event and the entity event in causal order:
+`KeyCreated` before `EntityAdded`, and `EntityRemoved` before `KeyRemoved`.
+
+*/
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+use crate::typed_index::{Changed, Index};
+use crate::EntityId;
+
+type PersonId = EntityId;
+
+/// A change to an `ObservableIndex`. The key is borrowed from the caller of the mutation.
+#[derive(Debug, PartialEq, Eq)]
+pub enum IndexEvent<'a, T> {
+  EntityAdded { key: &'a T, entity_id: PersonId },
+  EntityRemoved { key: &'a T, entity_id: PersonId },
+  KeyCreated { key: &'a T },
+  KeyRemoved { key: &'a T },
+}
+
+// Not derived, because the derives would require `T: Copy` even though only `&T` is stored.
+impl<T> Clone for IndexEvent<'_, T> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<T> Copy for IndexEvent<'_, T> {}
+
+type Observer<T> = Box<dyn FnMut(IndexEvent<'_, T>)>;
+
+/// A typed index that calls its observers on every change.
+pub struct ObservableIndex<T: Hash + Eq + Clone + Any> {
+  index: Index<T>,
+  observers: Vec<Observer<T>>,
+}
+
+impl<T: Hash + Eq + Clone + Any> Default for ObservableIndex<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T: Hash + Eq + Clone + Any> ObservableIndex<T> {
+  pub fn new() -> Self {
+    Self {
+      index: Index::new(),
+      observers: Vec::new(),
+    }
+  }
+
+  /// Registers an observer, which is called after every subsequent change.
+  pub fn observe(&mut self, observer: impl FnMut(IndexEvent<'_, T>) + 'static) {
+    self.observers.push(Box::new(observer));
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set. Emits `KeyCreated` if the set is new and
+  /// `EntityAdded` if the entity is.
+  #[must_use]
+  pub fn insert_entity(&mut self, key: &T, entity_id: PersonId) -> bool {
+    let key_created = !self.index.contains_key(key);
+    let added = self.index.insert_entity(key, entity_id);
+    if key_created {
+      self.emit(IndexEvent::KeyCreated { key });
+    }
+    if added {
+      self.emit(IndexEvent::EntityAdded { key, entity_id });
+    }
+    added
+  }
+
+  /// Removes an entity from the set associated with `key`, returning whether it was present. A set that becomes empty
+  /// is removed. Emits `EntityRemoved`, followed by `KeyRemoved` if the set was removed.
+  #[must_use]
+  pub fn remove_entity(&mut self, key: &T, entity_id: PersonId) -> bool {
+    if self.index.remove_entity_change(key, entity_id) != Changed::Removed {
+      return false;
+    }
+    self.emit(IndexEvent::EntityRemoved { key, entity_id });
+    if !self.index.contains_key(key) {
+      self.emit(IndexEvent::KeyRemoved { key });
+    }
+    true
+  }
+
+  /// Gets an immutable reference to the set associated with the `key` if it exists.
+  pub fn get(&self, key: &T) -> Option<&HashSet<PersonId>> {
+    self.index.get(key)
+  }
+
+  pub fn contains_key(&self, key: &T) -> bool {
+    self.index.contains_key(key)
+  }
+
+  /// Read-only access to the underlying index. Mutation must go through the wrapper so that no event is missed.
+  pub fn as_index(&self) -> &Index<T> {
+    &self.index
+  }
+
+  fn emit(&mut self, event: IndexEvent<'_, T>) {
+    for observer in &mut self.observers {
+      observer(event);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn events_fire_in_order() {
+    let log: Rc<RefCell<Vec<String>>> = Rc::default();
+    let mut index: ObservableIndex<&'static str> = ObservableIndex::new();
+    {
+      let log = log.clone();
+      index.observe(move |event| log.borrow_mut().push(format!("{:?}", event)));
+    }
+
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+    // Duplicate inserts and removals of absent entities change nothing, so they emit nothing.
+    let _ = index.insert_entity(&"infected", 2);
+    assert!(!index.remove_entity(&"infected", 3));
+    assert!(index.remove_entity(&"infected", 1));
+    assert!(index.remove_entity(&"infected", 2));
+
+    assert_eq!(*log.borrow(), vec![
+      r#"KeyCreated { key: "infected" }"#,
+      r#"EntityAdded { key: "infected", entity_id: 1 }"#,
+      r#"EntityAdded { key: "infected", entity_id: 2 }"#,
+      r#"EntityRemoved { key: "infected", entity_id: 1 }"#,
+      r#"EntityRemoved { key: "infected", entity_id: 2 }"#,
+      r#"KeyRemoved { key: "infected" }"#,
+    ]);
+    assert!(!index.contains_key(&"infected"));
+  }
+
+  #[test]
+  fn observers_keep_aggregates_in_sync() {
+    let count = Rc::new(RefCell::new(0i64));
+    let mut index: ObservableIndex<u32> = ObservableIndex::new();
+    {
+      let count = count.clone();
+      index.observe(move |event| match event {
+        IndexEvent::EntityAdded { .. } => *count.borrow_mut() += 1,
+        IndexEvent::EntityRemoved { .. } => *count.borrow_mut() -= 1,
+        _ => {}
+      });
+    }
+
+    let _ = index.insert_entity(&1, 10);
+    let _ = index.insert_entity(&2, 10);
+    let _ = index.insert_entity(&2, 11);
+    let _ = index.remove_entity(&2, 10);
+    assert_eq!(*count.borrow(), 2);
+  }
+}
@@ -0,0 +1,128 @@
+/*!
+
+A typed index split across several independently locked shards, so that writers touching different keys do not
+contend for a single lock.
+
+Each key is routed to one shard by its `one_shot_128` hash, and each shard is an ordinary `Index<T>` behind its own
+`RwLock`. This helps write-heavy workloads whose keys are well distributed; all writers to a single key still
+serialize on that key's shard.
+
+**Memory overhead:** every shard is a separate `HashTable` with its own allocation, control bytes, and load-factor
+slack, plus an `RwLock`. A sharded index with `N` shards therefore uses somewhat more memory than a single `Index<T>`
+holding the same data, and an empty one holds `N` empty tables.
+
+*/
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::RwLock;
+use crate::hash128::one_shot_128;
+use crate::typed_index::Index;
+use crate::EntityId;
+
+type PersonId = EntityId;
+
+/// A typed index whose keys are spread across `shard_count` independently locked `Index<T>` shards.
+#[derive(Debug)]
+pub struct ShardedIndex<T: Hash + Eq + Clone + Any> {
+  shards: Vec<RwLock<Index<T>>>,
+}
+
+impl<T: Hash + Eq + Clone + Any> ShardedIndex<T> {
+  /// Creates an index with `shard_count` shards. Panics if `shard_count` is zero.
+  pub fn new(shard_count: usize) -> Self {
+    assert!(shard_count > 0, "a sharded index needs at least one shard");
+    Self {
+      shards: (0..shard_count).map(|_| RwLock::new(Index::new())).collect(),
+    }
+  }
+
+  pub fn shard_count(&self) -> usize {
+    self.shards.len()
+  }
+
+  /// Selects the shard for a key's hash.
+  fn shard_for(&self, key: &T) -> &RwLock<Index<T>> {
+    let hash = one_shot_128(key);
+    // The shards' tables select buckets with the low 64 bits of the hash, so we take the shard from the high 64 bits.
+    // Otherwise all keys within a shard would share the same low bits and cluster in that shard's table.
+    let selector = (hash >> 64) as u64;
+    &self.shards[(selector % self.shards.len() as u64) as usize]
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set. Only the key's shard is locked.
+  ///
+  /// Panics if the shard's lock is poisoned.
+  #[must_use]
+  pub fn insert_entity(&self, key: &T, entity_id: PersonId) -> bool {
+    self.shard_for(key)
+        .write()
+        .expect("shard lock poisoned")
+        .insert_entity(key, entity_id)
+  }
+
+  /// Returns a copy of the set associated with the `key` if it exists. The set is cloned because it cannot outlive
+  /// the shard's read lock; use `with_set` to inspect it in place.
+  ///
+  /// Panics if the shard's lock is poisoned.
+  pub fn get(&self, key: &T) -> Option<HashSet<PersonId>> {
+    self.with_set(key, |set| set.cloned())
+  }
+
+  /// Calls `f` with the set associated with the `key`, if any, while holding the shard's read lock.
+  ///
+  /// Panics if the shard's lock is poisoned.
+  pub fn with_set<R>(&self, key: &T, f: impl FnOnce(Option<&HashSet<PersonId>>) -> R) -> R {
+    let shard = self.shard_for(key).read().expect("shard lock poisoned");
+    f(shard.get(key))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn routes_keys_to_shards() {
+    let index: ShardedIndex<u32> = ShardedIndex::new(4);
+    assert_eq!(index.shard_count(), 4);
+
+    assert!(index.insert_entity(&1, 10));
+    assert!(index.insert_entity(&1, 11));
+    assert!(!index.insert_entity(&1, 11));
+    assert!(index.insert_entity(&2, 20));
+
+    assert_eq!(index.get(&1), Some(HashSet::from([10, 11])));
+    assert_eq!(index.get(&2), Some(HashSet::from([20])));
+    assert_eq!(index.get(&3), None);
+    assert_eq!(index.with_set(&1, |set| set.map_or(0, |set| set.len())), 2);
+  }
+
+  #[test]
+  fn concurrent_writers() {
+    let index: ShardedIndex<u32> = ShardedIndex::new(8);
+
+    std::thread::scope(|scope| {
+      for thread in 0..4u64 {
+        let index = &index;
+        scope.spawn(move || {
+          for entity_id in 0..100u64 {
+            let _ = index.insert_entity(&((entity_id % 10) as u32), thread * 1000 + entity_id);
+          }
+        });
+      }
+    });
+
+    for key in 0..10u32 {
+      assert_eq!(index.get(&key).unwrap().len(), 40);
+    }
+  }
+
+  #[test]
+  #[should_panic]
+  fn zero_shards_panics() {
+    let _ = ShardedIndex::<u32>::new(0);
+  }
+}
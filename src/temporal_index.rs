@@ -0,0 +1,119 @@
+/*!
+
+A typed index whose memberships expire.
+
+`TemporalIndex<T>` records the time at which each `(key, entity)` membership was inserted, and `expire_before(t)`
+removes every membership inserted before `t`. This models properties whose values only count for a window of time,
+e.g. "was exposed in the last 14 days". Times are `f64`, like simulation time.
+
+*/
+
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use crate::hash128::one_shot_128;
+use crate::typed_index::Index;
+use crate::{EntityId, HashValueType};
+
+type PersonId = EntityId;
+
+/// A typed index that associates each membership with the time it was inserted.
+#[derive(Debug)]
+pub struct TemporalIndex<T: Hash + Eq + Clone + Any> {
+  index: Index<T>,
+  /// The insertion time of each membership, keyed by the key's hash and the entity.
+  inserted_at: HashMap<(HashValueType, PersonId), f64>,
+}
+
+impl<T: Hash + Eq + Clone + Any> Default for TemporalIndex<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T: Hash + Eq + Clone + Any> TemporalIndex<T> {
+  pub fn new() -> Self {
+    Self {
+      index: Index::new(),
+      inserted_at: HashMap::new(),
+    }
+  }
+
+  /// Inserts an entity into the set associated with `key` at `time`, creating a new set if one does not yet exist.
+  /// Returns a `bool` according to whether the `entity_id` already existed in the set. Inserting an existing
+  /// membership again refreshes its time.
+  #[must_use]
+  pub fn insert_entity_at(&mut self, key: &T, entity_id: PersonId, time: f64) -> bool {
+    self.inserted_at.insert((one_shot_128(key), entity_id), time);
+    self.index.insert_entity(key, entity_id)
+  }
+
+  /// Removes every membership inserted before `time`, dropping sets that become empty. Returns the number of
+  /// memberships removed. This visits every membership, so it is linear in the size of the index.
+  pub fn expire_before(&mut self, time: f64) -> usize {
+    let inserted_at = &mut self.inserted_at;
+    let expired = self.index.drain_entities(|key, entity_id| {
+      let membership = (one_shot_128(key), entity_id);
+      if inserted_at[&membership] < time {
+        inserted_at.remove(&membership);
+        true
+      } else {
+        false
+      }
+    });
+    expired.len()
+  }
+
+  /// Gets an immutable reference to the set associated with the `key` if it exists.
+  pub fn get(&self, key: &T) -> Option<&HashSet<PersonId>> {
+    self.index.get(key)
+  }
+
+  pub fn contains_key(&self, key: &T) -> bool {
+    self.index.contains_key(key)
+  }
+
+  /// The time at which the membership of `entity_id` in the set for `key` was (last) inserted.
+  pub fn inserted_at(&self, key: &T, entity_id: PersonId) -> Option<f64> {
+    self.inserted_at.get(&(one_shot_128(key), entity_id)).copied()
+  }
+
+  /// Read-only access to the underlying index.
+  pub fn as_index(&self) -> &Index<T> {
+    &self.index
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn expiry_drops_old_memberships() {
+    let mut index: TemporalIndex<&'static str> = TemporalIndex::new();
+    let _ = index.insert_entity_at(&"exposed", 1, 0.0);
+    let _ = index.insert_entity_at(&"exposed", 2, 5.0);
+    let _ = index.insert_entity_at(&"infected", 3, 1.0);
+
+    assert_eq!(index.expire_before(2.0), 2);
+    assert_eq!(index.get(&"exposed"), Some(&HashSet::from([2])));
+    // The emptied set is gone.
+    assert!(!index.contains_key(&"infected"));
+    assert_eq!(index.inserted_at(&"exposed", 1), None);
+    assert_eq!(index.inserted_at(&"exposed", 2), Some(5.0));
+
+    assert_eq!(index.expire_before(2.0), 0);
+  }
+
+  #[test]
+  fn reinsertion_refreshes_time() {
+    let mut index: TemporalIndex<&'static str> = TemporalIndex::new();
+    assert!(index.insert_entity_at(&"exposed", 1, 0.0));
+    assert!(!index.insert_entity_at(&"exposed", 1, 10.0));
+
+    assert_eq!(index.expire_before(5.0), 0);
+    assert_eq!(index.get(&"exposed"), Some(&HashSet::from([1])));
+    assert_eq!(index.expire_before(11.0), 1);
+    assert!(!index.contains_key(&"exposed"));
+  }
+}
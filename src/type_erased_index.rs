@@ -1,31 +1,97 @@
 //! A completely type-erased index.
 
 use std::{
-  collections::HashSet,
+  collections::{HashMap, HashSet},
   hash::Hash
 };
 use hashbrown::{
   HashTable,
+  TryReserveError,
   hash_table::OccupiedEntry
 };
-use crate::hash128::{one_shot_128};
-use crate::EntityId;
-
-type HashValueType = u128;
+use crate::hash128::{index_fingerprint, one_shot_128_seeded};
+#[cfg(feature = "serde")]
+use crate::hash128::algorithm_fingerprint;
+use crate::typed_index::{remap_set, UnmappedIds};
+use crate::{EntityId, HashValueType};
 
 
 /// A completely type-erased index.
+#[derive(Default, Debug)]
 pub struct Index {
   lookup: HashTable<(HashValueType, HashSet<EntityId>)>,
+  /// The seed the keys were hashed with; see `with_seed`.
+  seed: u64,
 }
 
 impl Index {
   pub fn new() -> Self {
+    Self::with_seed(0)
+  }
+
+  /// An empty index whose keys are hashed with `one_shot_128_seeded(key, seed)`, matching a typed index created with
+  /// `typed_index::Index::with_seed`. Only the methods that take a key use the seed; hashes passed to the others must
+  /// already have been computed with it.
+  pub fn with_seed(seed: u64) -> Self {
+    Self::with_capacity_and_seed(0, seed)
+  }
+
+  /// An empty index with room for at least `capacity` hashes.
+  pub fn with_capacity(capacity: usize) -> Self {
+    Self::with_capacity_and_seed(capacity, 0)
+  }
+
+  /// An empty index with room for at least `capacity` hashes, whose keys are hashed with `seed`.
+  pub fn with_capacity_and_seed(capacity: usize, seed: u64) -> Self {
     Self {
-      lookup: HashTable::new(),
+      lookup: HashTable::with_capacity(capacity),
+      seed,
+    }
+  }
+
+  /// The seed the keys were hashed with.
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  /// Checks that the index was hashed with `expected`, e.g. after loading a checkpoint, since hashes computed with a
+  /// different seed would silently miss every set. Returns `Err(seed)` with the actual seed otherwise.
+  pub fn verify_seed(&self, expected: u64) -> Result<(), u64> {
+    if self.seed == expected {
+      Ok(())
+    } else {
+      Err(self.seed)
     }
   }
 
+  /// Bulk-loads an index from `(hash, set)` pairs, e.g. from a checkpoint. The table is allocated once at its final
+  /// size and the pairs are inserted without any equality checks, so the table never grows and no hashes are compared
+  /// during the load. The pairs may be in any order.
+  ///
+  /// The hashes must be unique; this is checked only in debug builds. The index is unseeded; use
+  /// `from_parts_with_seed` for parts exported from a seeded index.
+  pub fn from_parts(parts: Vec<(HashValueType, HashSet<EntityId>)>) -> Self {
+    Self::from_parts_with_seed(parts, 0)
+  }
+
+  /// Like `from_parts`, but for hashes computed with `seed`, so that lookups by key hash the same way.
+  pub fn from_parts_with_seed(parts: Vec<(HashValueType, HashSet<EntityId>)>, seed: u64) -> Self {
+    debug_assert!(
+      {
+        let mut seen = HashSet::with_capacity(parts.len());
+        parts.iter().all(|(hash, _)| seen.insert(*hash))
+      },
+      "`from_parts` requires unique hashes"
+    );
+
+    let hasher = |(stored_hash, _stored_set): &_| *stored_hash as u64;
+    let mut lookup = HashTable::with_capacity(parts.len());
+    for (hash, set) in parts {
+      lookup.insert_unique(hash as u64, (hash, set), hasher);
+    }
+    Self { lookup, seed }
+  }
+
   pub fn insert_with_hash(&mut self, hash: HashValueType, set: HashSet<EntityId>) -> OccupiedEntry<'_, (HashValueType, HashSet<EntityId>)> {
     // > `hasher` is called if entries need to be moved or copied to a new table.
     // > This must return the same hash value that each entry was inserted with.
@@ -33,9 +99,15 @@ impl Index {
     self.lookup.insert_unique(hash as u64, (hash, set), hasher)
   }
 
+  /// Reserves capacity for at least `additional` more hashes, returning an error instead of aborting if the
+  /// allocation fails.
+  pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+    self.lookup.try_reserve(additional, |(stored_hash, _stored_set)| *stored_hash as u64)
+  }
+
   /// The caller is responsible for ensuring that the key has the right type for this index.
   pub fn insert<T: Hash>(&mut self, key: T, set: HashSet<EntityId>) -> OccupiedEntry<'_, (HashValueType, HashSet<EntityId>)> {
-    let hash = one_shot_128(&key);
+    let hash = one_shot_128_seeded(&key, self.seed);
     self.insert_with_hash(hash, set)
   }
 
@@ -48,15 +120,302 @@ impl Index {
     self.lookup.find_mut(hash as u64, |(stored_hash, _)| *stored_hash == hash).map(|(_, set)| set)
   }
 
+  /// Removes `entity_id` from the set associated with `hash`, returning whether it was a member. A set that this call
+  /// empties is removed from the index; a set that was already empty is left alone.
+  pub fn remove_with_hash(&mut self, hash: HashValueType, entity_id: EntityId) -> bool {
+    let Ok(mut entry) = self.lookup.find_entry(hash as u64, |(stored_hash, _)| *stored_hash == hash) else {
+      return false;
+    };
+    let removed = entry.get_mut().1.remove(&entity_id);
+    if removed && entry.get().1.is_empty() {
+      entry.remove();
+    }
+    removed
+  }
+
+  /// The caller is responsible for ensuring that the key has the right type for this index.
+  pub fn remove<T: Hash>(&mut self, key: &T, entity_id: EntityId) -> bool {
+    let hash = one_shot_128_seeded(&key, self.seed);
+    self.remove_with_hash(hash, entity_id)
+  }
+
   /// The caller is responsible for ensuring that the key has the right type for this index.
   pub fn get<T: Hash>(&self, key: &T) -> Option<&HashSet<EntityId>> {
-    let hash = one_shot_128(&key);
+    let hash = one_shot_128_seeded(&key, self.seed);
     self.get_with_hash(hash)
   }
 
+  /// Iterates over the `(hash, set)` pairs in ascending order of hash. The stored hashes do not depend on insertion
+  /// order or table layout, so this order is reproducible, e.g. for deterministic dumps of a multi-index. Sorting
+  /// requires collecting the pairs first.
+  pub fn iter_sorted_by_hash(&self) -> impl Iterator<Item = (HashValueType, &HashSet<EntityId>)> {
+    let mut buckets: Vec<_> = self.lookup.iter().map(|(hash, set)| (*hash, set)).collect();
+    buckets.sort_unstable_by_key(|(hash, _)| *hash);
+    buckets.into_iter()
+  }
+
+  /// A digest of the index's full contents. Indexes with the same hashes and sets have the same fingerprint regardless
+  /// of insertion order.
+  pub fn fingerprint(&self) -> HashValueType {
+    index_fingerprint(self.lookup.iter().map(|(hash, set)| (*hash, set)))
+  }
+
+  /// The sizes of all sets in the index in ascending order, one per hash.
+  pub fn bucket_sizes_sorted(&self) -> Vec<usize> {
+    let mut sizes: Vec<usize> = self.lookup.iter().map(|(_, set)| set.len()).collect();
+    sizes.sort_unstable();
+    sizes
+  }
+
+  /// Renumbers every member of every set through `mapping`. Ids missing from `mapping` are kept or removed according
+  /// to `unmapped`, and a set left empty by removal is dropped from the index.
+  pub fn remap_ids(&mut self, mapping: &HashMap<EntityId, EntityId>, unmapped: UnmappedIds) {
+    let mut scratch = Vec::new();
+    self.lookup.retain(|(_, set)| {
+      let was_empty = set.is_empty();
+      remap_set(set, mapping, unmapped, &mut scratch);
+      // Only drop sets that this call emptied.
+      was_empty || !set.is_empty()
+    });
+  }
+
   /// The caller is responsible for ensuring that the key has the right type for this index.
   pub fn get_mut<T: Hash>(&mut self, key: &T) -> Option<&mut HashSet<EntityId>> {
-    let hash = one_shot_128(&key);
+    let hash = one_shot_128_seeded(&key, self.seed);
     self.get_with_hash_mut(hash)
   }
 }
+
+/// Generates a random index state from random `(hash, HashSet<EntityId>)` pairs. Generated hashes may repeat, in
+/// which case their sets are merged.
+#[cfg(feature = "fuzz")]
+impl<'a> arbitrary::Arbitrary<'a> for Index {
+  fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+    let entries: Vec<(HashValueType, HashSet<EntityId>)> = u.arbitrary()?;
+    let mut index = Self::new();
+    for (hash, set) in entries {
+      // `insert_with_hash` assumes the hash is new, so duplicate hashes have to be merged into the existing set.
+      match index.get_with_hash_mut(hash) {
+        Some(existing) => existing.extend(set),
+        None => { index.insert_with_hash(hash, set); }
+      }
+    }
+    Ok(index)
+  }
+}
+
+/// Writes a header of the `hash128::algorithm_fingerprint` and the seed, followed by the `(hash, members)` pairs in
+/// ascending order of hash with their members sorted, so that the output is deterministic. A loader can check the seed
+/// with `verify_seed`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Index {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let buckets: Vec<(HashValueType, Vec<EntityId>)> = self
+        .iter_sorted_by_hash()
+        .map(|(hash, set)| {
+          let mut members: Vec<EntityId> = set.iter().copied().collect();
+          members.sort_unstable();
+          (hash, members)
+        })
+        .collect();
+    serde::Serialize::serialize(&(algorithm_fingerprint(), self.seed, buckets), serializer)
+  }
+}
+
+/// Deserializes the form written by `Serialize`, keeping the seed from its header so that lookups by key hash the
+/// same way as the index that was written. Hashes that appear more than once have their members merged.
+///
+/// Fails if the header's algorithm fingerprint differs from this build's, since the stored hashes would then not match
+/// the hashes this build computes for the same keys.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Index {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let (fingerprint, seed, buckets): (HashValueType, u64, Vec<(HashValueType, Vec<EntityId>)>) =
+        serde::Deserialize::deserialize(deserializer)?;
+    if fingerprint != algorithm_fingerprint() {
+      return Err(serde::de::Error::custom(format!(
+        "hash algorithm fingerprint {:#x} does not match this build's {:#x}",
+        fingerprint,
+        algorithm_fingerprint()
+      )));
+    }
+    let mut index = Self::with_capacity_and_seed(buckets.len(), seed);
+    for (hash, members) in buckets {
+      // `insert_with_hash` assumes the hash is new, which untrusted input need not respect.
+      match index.get_with_hash_mut(hash) {
+        Some(existing) => existing.extend(members),
+        None => { index.insert_with_hash(hash, members.into_iter().collect()); }
+      }
+    }
+    Ok(index)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::hash128::one_shot_128;
+
+  #[test]
+  fn iter_sorted_by_hash_is_ascending() {
+    let mut index = Index::new();
+    for key in 0..20u32 {
+      index.insert(key, HashSet::from([key as EntityId]));
+    }
+
+    let hashes: Vec<HashValueType> = index.iter_sorted_by_hash().map(|(hash, _)| hash).collect();
+    assert_eq!(hashes.len(), 20);
+    assert!(hashes.windows(2).all(|pair| pair[0] < pair[1]));
+
+    for (hash, set) in index.iter_sorted_by_hash() {
+      assert_eq!(index.get_with_hash(hash), Some(set));
+    }
+  }
+
+  #[test]
+  fn bucket_sizes_sorted_is_ascending() {
+    let mut index = Index::new();
+    index.insert("a", HashSet::from([1, 2, 3]));
+    index.insert("b", HashSet::from([4]));
+    index.insert("c", HashSet::from([5, 6]));
+
+    let sizes = index.bucket_sizes_sorted();
+    assert_eq!(sizes.len(), index.lookup.len());
+    assert_eq!(sizes, vec![1, 2, 3]);
+  }
+
+  #[test]
+  fn remove_drops_emptied_bucket() {
+    let mut index = Index::new();
+    index.insert("infected", HashSet::from([1, 2]));
+
+    // Not a member, and not a key.
+    assert!(!index.remove(&"infected", 3));
+    assert!(!index.remove(&"recovered", 1));
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1, 2])));
+
+    assert!(index.remove(&"infected", 1));
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([2])));
+    assert!(index.remove_with_hash(one_shot_128(&"infected"), 2));
+    assert_eq!(index.get(&"infected"), None);
+    assert!(index.lookup.is_empty());
+  }
+
+  #[test]
+  fn remove_non_member_keeps_empty_bucket() {
+    let mut index = Index::new();
+    index.insert("exposed", HashSet::new());
+
+    assert!(!index.remove(&"exposed", 1));
+    assert_eq!(index.get(&"exposed"), Some(&HashSet::new()));
+  }
+
+  #[test]
+  fn remap_ids_rewrites_members() {
+    let mut index = Index::new();
+    index.insert("infected", HashSet::from([10, 20]));
+    index.insert("recovered", HashSet::from([30]));
+    index.insert("exposed", HashSet::new());
+
+    index.remap_ids(&HashMap::from([(10, 0), (30, 2)]), UnmappedIds::Keep);
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([0, 20])));
+    assert_eq!(index.get(&"recovered"), Some(&HashSet::from([2])));
+
+    index.remap_ids(&HashMap::from([(0, 1)]), UnmappedIds::Remove);
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1])));
+    assert_eq!(index.get(&"recovered"), None);
+    assert_eq!(index.get(&"exposed"), Some(&HashSet::new()));
+  }
+
+  #[test]
+  fn try_reserve_small_succeeds() {
+    let mut index = Index::new();
+    assert!(index.try_reserve(100).is_ok());
+    assert!(index.lookup.capacity() >= 100);
+    assert!(index.try_reserve(usize::MAX).is_err());
+  }
+
+  #[test]
+  fn from_parts_matches_insert_loop() {
+    let parts: Vec<_> = (0..100u32).map(|key| (one_shot_128(&key), HashSet::from([key as EntityId]))).collect();
+
+    let mut expected = Index::new();
+    for (hash, set) in parts.clone() {
+      expected.insert_with_hash(hash, set);
+    }
+    let index = Index::from_parts(parts);
+    assert_eq!(index.fingerprint(), expected.fingerprint());
+    assert_eq!(index.get(&7u32), Some(&HashSet::from([7])));
+  }
+
+  #[test]
+  fn from_parts_with_seed_keeps_the_seed() {
+    let parts = vec![(one_shot_128_seeded(&"infected", 7), HashSet::from([1]))];
+    let index = Index::from_parts_with_seed(parts.clone(), 7);
+    assert_eq!(index.seed(), 7);
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1])));
+
+    // Loaded without the seed, lookups by key miss.
+    assert_eq!(Index::from_parts(parts).get(&"infected"), None);
+  }
+
+  #[test]
+  #[should_panic(expected = "unique hashes")]
+  #[cfg(debug_assertions)]
+  fn from_parts_rejects_duplicate_hashes() {
+    let hash = one_shot_128(&1u32);
+    Index::from_parts(vec![(hash, HashSet::from([1])), (hash, HashSet::from([2]))]);
+  }
+
+  #[test]
+  fn with_seed_hashes_keys_with_the_seed() {
+    let mut index = Index::with_seed(7);
+    index.insert("infected", HashSet::from([1]));
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1])));
+    assert_eq!(index.get_with_hash(one_shot_128_seeded(&"infected", 7)), Some(&HashSet::from([1])));
+    assert_eq!(index.get_with_hash(one_shot_128(&"infected")), None);
+    assert!(index.remove(&"infected", 1));
+    assert_eq!(Index::new().seed(), 0);
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serde_round_trip_writes_algorithm_fingerprint() {
+    let mut index = Index::with_seed(7);
+    index.insert("infected", HashSet::from([1, 2]));
+
+    let json = serde_json::to_string(&index).unwrap();
+    assert!(json.starts_with(&format!("[{},7,", algorithm_fingerprint())));
+    let loaded: Index = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.fingerprint(), index.fingerprint());
+    assert_eq!(loaded.seed(), 7);
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn deserialize_rejects_tampered_algorithm_fingerprint() {
+    let mut index = Index::new();
+    index.insert("infected", HashSet::from([1]));
+
+    let json = serde_json::to_string(&index).unwrap();
+    let tampered = json.replacen(&algorithm_fingerprint().to_string(), "1", 1);
+    assert_ne!(tampered, json);
+    let error = serde_json::from_str::<Index>(&tampered).unwrap_err();
+    assert!(error.to_string().contains("fingerprint"));
+  }
+
+  #[test]
+  fn fingerprint_is_order_independent() {
+    let mut a = Index::new();
+    a.insert("x", HashSet::from([1, 2]));
+    a.insert("y", HashSet::from([3]));
+
+    let mut b = Index::new();
+    b.insert("y", HashSet::from([3]));
+    b.insert("x", HashSet::from([2, 1]));
+
+    assert_eq!(a.fingerprint(), b.fingerprint());
+    b.get_mut(&"y").unwrap().insert(4);
+    assert_ne!(a.fingerprint(), b.fingerprint());
+  }
+}
@@ -17,46 +17,179 @@ serialized values, and internally the value is deserialized. This was the origin
 
 */
 
-use std::any::Any;
-use std::collections::HashSet;
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
-use hashbrown::{HashTable};
-use hashbrown::hash_table::OccupiedEntry;
-use crate::hash128::one_shot_128;
-use crate::EntityId;
+use hashbrown::{HashTable, TryReserveError};
+use hashbrown::hash_table::{self, OccupiedEntry};
+use crate::canonical_index::CanonicalIndex;
+use crate::frozen_index::FrozenIndex;
+use crate::hash128::{index_fingerprint, one_shot_128_seeded};
+use crate::type_erased_index;
+use crate::{EntityId, HashValueType};
 
 type PersonId = EntityId;
 
 pub type BxIndex = Box<dyn TypeErasedIndex>;
-type HashValueType = u128;
+
+/// The outcome of a mutation of a single entity's membership, for callers that want to match on what happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Changed {
+  /// The entity was added to the set.
+  Inserted,
+  /// The entity was already in the set, so nothing changed.
+  AlreadyPresent,
+  /// The entity was removed from the set.
+  Removed,
+  /// The key or the entity was not in the index, so nothing changed.
+  NotFound,
+}
+
+/// What `remap_ids` does with members that the mapping does not mention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnmappedIds {
+  /// Leave the id as it is.
+  Keep,
+  /// Remove the id from the set.
+  Remove,
+}
+
+/// Rewrites the members of `set` through `mapping` in place, reusing the set's allocation. `scratch` is a buffer the
+/// caller can reuse across sets.
+pub(crate) fn remap_set(
+  set: &mut HashSet<PersonId>,
+  mapping: &HashMap<PersonId, PersonId>,
+  unmapped: UnmappedIds,
+  scratch: &mut Vec<PersonId>,
+) {
+  scratch.clear();
+  scratch.extend(set.drain().filter_map(|entity_id| match (mapping.get(&entity_id), unmapped) {
+    (Some(&new_id), _) => Some(new_id),
+    (None, UnmappedIds::Keep) => Some(entity_id),
+    (None, UnmappedIds::Remove) => None,
+  }));
+  set.extend(scratch.iter().copied());
+}
+
+/// What `Index::remove_entity_detailed` did to an existing key's set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoveOutcome {
+  /// Whether the entity was in the set.
+  pub was_present: bool,
+  /// The size of the set afterwards.
+  pub remaining: usize,
+  /// Whether the set became empty and was removed from the index.
+  pub key_dropped: bool,
+}
+
+/// What `Index::merge_reporting` did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MergeReport {
+  /// Keys that were only in the other index, and so were created.
+  pub keys_created: usize,
+  /// Keys that were in both indexes, whose sets were merged.
+  pub keys_updated: usize,
+  /// Memberships that were new, counting every member of a created key.
+  pub entities_added: usize,
+  /// Memberships that were already present. Zero if the two indexes were disjoint.
+  pub entities_duplicate: usize,
+}
 
 /// The typed index.
-#[derive(Default)]
+#[derive(Debug)]
 pub struct Index<T: Hash + Eq + Clone + Any> {
   // We store a copy of the value here so that we can iterate over it in the typed API, and so that the type-erased
   // API can access some serialization of it.
   lookup: HashTable<(T, HashSet<PersonId>)>,
+  /// The seed every key is hashed with; see `with_seed`.
+  seed: u64,
+}
+
+// Not derived, so that `clone_from` can reuse the destination's allocations.
+impl<T: Hash + Eq + Clone + Any> Clone for Index<T> {
+  fn clone(&self) -> Self {
+    Self {
+      lookup: self.lookup.clone(),
+      seed  : self.seed,
+    }
+  }
+
+  fn clone_from(&mut self, source: &Self) {
+    source.clone_into(self);
+  }
+}
+
+// Not derived, because the derive would needlessly require `T: Default`.
+impl<T: Hash + Eq + Clone + Any> Default for Index<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Consumes the index, yielding owned `(key, set)` pairs in unspecified order.
+impl<T: Hash + Eq + Clone + Any> IntoIterator for Index<T> {
+  type Item = (T, HashSet<PersonId>);
+  type IntoIter = hash_table::IntoIter<(T, HashSet<PersonId>)>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.lookup.into_iter()
+  }
 }
 
 /// Contains the typed API
 impl<T: Hash + Eq + Clone + Any> Index<T> {
   pub fn new() -> Self {
+    Self::with_seed(0)
+  }
+
+  /// Creates an empty index that hashes its keys with `one_shot_128_seeded(key, seed)` instead of `one_shot_128`, e.g.
+  /// to vary hashes between runs. Seed 0 is the same as `new`. The hash-based methods expect hashes computed with the
+  /// index's seed, and `forget_keys` passes it on to the type-erased index, which records it when serialized.
+  pub fn with_seed(seed: u64) -> Self {
     Self {
       lookup: HashTable::default(),
+      seed,
+    }
+  }
+
+
+  /// Builds an index from `(key, set)` pairs whose keys are expected to be distinct, e.g. externally grouped data.
+  /// Returns `Err(key)` with the first key that appears a second time, rather than merging the sets.
+  pub fn from_grouped_checked<I: IntoIterator<Item = (T, HashSet<PersonId>)>>(iter: I) -> Result<Self, T> {
+    let mut index = Self::new();
+    for (key, set) in iter {
+      match index.entry(key) {
+        Entry::Occupied(entry) => return Err(entry.get().0.clone()),
+        Entry::Vacant(entry) => { entry.insert(set); }
+      }
     }
+    Ok(index)
+  }
+
+  /// Creates an empty index that applies `canonicalizer` to every key before hashing it, so that keys with the same
+  /// canonical form share a set, e.g. `|s: &String| s.to_lowercase()` for case-insensitive strings. See
+  /// `CanonicalIndex`.
+  pub fn with_canonicalizer(canonicalizer: impl Fn(&T) -> T + 'static) -> CanonicalIndex<T> {
+    CanonicalIndex::new(Box::new(canonicalizer))
   }
 
   /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
   /// `bool` according to whether the `entity_id` already existed in the set.
+  ///
+  /// The key is cloned only when a new set is created, since the index stores its own copy of each key. Inserting
+  /// into an existing set never clones. Callers that already own the key can use `insert_entity_cow` to avoid the
+  /// clone altogether.
+  #[must_use]
   pub fn insert_entity(&mut self, key: &T, entity_id: PersonId) -> bool {
-    let hash = one_shot_128(&key);
+    let hash = one_shot_128_seeded(&key, self.seed);
 
     // > `hasher` is called if entries need to be moved or copied to a new table.
     // > This must return the same hash value that each entry was inserted with.
-    let hasher = |(stored_value, _stored_set): &_| one_shot_128(stored_value) as u64;
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
     // Equality is determined by comparing the full 128-bit hashes. We do not expect any collisions before the heat
     // death of the universe.
-    let hash128_equality = |(stored_value, _): &_| one_shot_128(stored_value) == hash;
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
     self.lookup
         .entry(hash as u64, hash128_equality, hasher)
         .or_insert_with(|| (key.clone(), HashSet::new()))
@@ -65,86 +198,2309 @@ impl<T: Hash + Eq + Clone + Any> Index<T> {
         .insert(entity_id)
   }
 
+  /// Like `insert_entity`, but also returns the size of the set associated with `key` after the insertion. The
+  /// returned tuple is `(newly_added, resulting_set_len)`. The length is read from the already resolved bucket, so
+  /// no second lookup is performed.
+  #[must_use]
+  pub fn insert_entity_with_size(&mut self, key: &T, entity_id: PersonId) -> (bool, usize) {
+    let hash = one_shot_128_seeded(&key, self.seed);
+
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+    let mut entry = self.lookup
+                        .entry(hash as u64, hash128_equality, hasher)
+                        .or_insert_with(|| (key.clone(), HashSet::new()));
+    let set = &mut entry.get_mut().1;
+    let newly_added = set.insert(entity_id);
+    (newly_added, set.len())
+  }
+
+  /// Like `insert_entity`, but reports the outcome as `Changed::Inserted` or `Changed::AlreadyPresent`.
+  #[must_use]
+  pub fn insert_entity_change(&mut self, key: &T, entity_id: PersonId) -> Changed {
+    if self.insert_entity(key, entity_id) {
+      Changed::Inserted
+    } else {
+      Changed::AlreadyPresent
+    }
+  }
+
+  /// Removes an entity from the set associated with `key`, returning whether it was present. A set that becomes empty
+  /// is removed from the index, so `contains_key` is false afterwards. Returns `false` if the key is absent.
+  #[must_use]
+  pub fn remove_entity(&mut self, key: &T, entity_id: PersonId) -> bool {
+    self.remove_entity_change(key, entity_id) == Changed::Removed
+  }
+
+  /// Removes an entity from the set associated with `key`, reporting `Changed::Removed` if it was there and
+  /// `Changed::NotFound` if the key or the entity was absent. A set that becomes empty is removed from the index.
+  #[must_use]
+  pub fn remove_entity_change(&mut self, key: &T, entity_id: PersonId) -> Changed {
+    let Some(mut entry) = self.occupied_entry(key) else {
+      return Changed::NotFound;
+    };
+    let set = &mut entry.get_mut().1;
+    if !set.remove(&entity_id) {
+      return Changed::NotFound;
+    }
+    if set.is_empty() {
+      entry.remove();
+    }
+    Changed::Removed
+  }
+
+  /// Removes an entity from the set associated with `key`, like `remove_entity_change`, but reports the size of the
+  /// set afterwards and whether it was dropped. A set that becomes empty is removed from the index. Returns `None` if
+  /// the key is absent.
+  pub fn remove_entity_detailed(&mut self, key: &T, entity_id: PersonId) -> Option<RemoveOutcome> {
+    let mut entry = self.occupied_entry(key)?;
+    let set = &mut entry.get_mut().1;
+    let was_present = set.remove(&entity_id);
+    let remaining = set.len();
+    let key_dropped = remaining == 0;
+    if key_dropped {
+      entry.remove();
+    }
+    Some(RemoveOutcome { was_present, remaining, key_dropped })
+  }
+
+  /// Removes `key` from the index and returns its set, moved out rather than cloned, so that the caller can consume
+  /// it or reuse its allocation. Returns `None` if the key is absent. Named after `HashMap::remove`; this is also the
+  /// way to "take" a set out of the index.
+  pub fn remove_key(&mut self, key: &T) -> Option<HashSet<PersonId>> {
+    let entry = self.occupied_entry(key)?;
+    let ((_, set), _) = entry.remove();
+    Some(set)
+  }
+
+  /// Like `insert_entity`, but takes the key as a `Cow`. When a new set is created, an owned key is moved into the
+  /// index instead of being cloned; a borrowed key is cloned as in `insert_entity`.
+  pub fn insert_entity_cow(&mut self, key: Cow<'_, T>, entity_id: PersonId) -> bool {
+    let hash = one_shot_128_seeded(key.as_ref(), self.seed);
+
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+    self.lookup
+        .entry(hash as u64, hash128_equality, hasher)
+        .or_insert_with(|| (key.into_owned(), HashSet::new()))
+        .get_mut()
+        .1
+        .insert(entity_id)
+  }
+
   /// Inserting a new _value_ requires the value itself.
   pub fn insert_value(&mut self, key: T, set: HashSet<PersonId>) -> OccupiedEntry<'_, (T, HashSet<PersonId>)> {
-    let hash = one_shot_128(&key);
+    let hash = one_shot_128_seeded(&key, self.seed);
     // > `hasher` is called if entries need to be moved or copied to a new table.
     // > This must return the same hash value that each entry was inserted with.
-    let hasher = |(stored_value, _stored_set): &_| one_shot_128(stored_value) as u64;
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
     self.lookup.insert_unique(hash as u64, (key, set), hasher)
   }
 
+  /// Makes `set` the set associated with `key`, creating the entry if it does not exist. Returns the previous set, if
+  /// there was one. Unlike `insert_value`, this is correct whether or not `key` is already in the index.
+  pub fn replace_set(&mut self, key: &T, set: HashSet<PersonId>) -> Option<HashSet<PersonId>> {
+    let hash = one_shot_128_seeded(&key, self.seed);
+
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+    match self.lookup.entry(hash as u64, hash128_equality, hasher) {
+      hash_table::Entry::Occupied(mut entry) => Some(std::mem::replace(&mut entry.get_mut().1, set)),
+      hash_table::Entry::Vacant(entry) => {
+        entry.insert((key.clone(), set));
+        None
+      }
+    }
+  }
+
+  /// The number of keys in the index, i.e. of distinct property values, not the number of entities. See
+  /// `entity_count` for the latter.
+  pub fn len(&self) -> usize {
+    self.lookup.len()
+  }
+
+  /// Does the index contain no keys?
+  pub fn is_empty(&self) -> bool {
+    self.lookup.is_empty()
+  }
+
+  /// Removes every key and set. The table keeps its allocated capacity, so an index reused across replicates does not
+  /// have to grow again. See `TypeErasedIndex::clear_and_reserve` to also reserve room up front.
+  pub fn clear(&mut self) {
+    self.lookup.clear();
+  }
+
+  /// The number of keys the index can hold before its table has to grow.
+  pub fn capacity(&self) -> usize {
+    self.lookup.capacity()
+  }
+
+  /// Shrinks the table to fit its keys if fewer than `min_load_factor` of its capacity is in use, e.g. after many keys
+  /// were removed, and returns whether it shrank. Cheap enough to call periodically: when the table is not sparse,
+  /// it only compares two numbers.
+  pub fn shrink_if_sparse(&mut self, min_load_factor: f64) -> bool {
+    let capacity = self.lookup.capacity();
+    if capacity == 0 || (self.lookup.len() as f64) / (capacity as f64) >= min_load_factor {
+      return false;
+    }
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    self.lookup.shrink_to_fit(hasher);
+    self.lookup.capacity() < capacity
+  }
+
+  /// Reserves capacity for at least `additional` more keys, returning an error instead of aborting if the allocation
+  /// fails.
+  pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    self.lookup.try_reserve(additional, hasher)
+  }
+
+  /// The hash this index stores `key` under, i.e. the hash that `get_with_hash` and the other hash-based methods
+  /// expect for it: `one_shot_128_seeded(key, self.seed())`. Callers can compute it once and cache it.
+  pub fn hash_of_key(&self, key: &T) -> HashValueType {
+    one_shot_128_seeded(key, self.seed)
+  }
+
   /// Gets an immutable reference to the set associated with the `key` if it exists.
   pub fn get(&self, key: &T) -> Option<&HashSet<PersonId>> {
-    let hash = one_shot_128(&key);
+    let hash = one_shot_128_seeded(&key, self.seed);
     self.get_with_hash(hash)
   }
 
+  /// The number of entities in the set associated with `key`, or 0 if the key is absent. See `entity_count` for the
+  /// total over all keys.
+  pub fn entity_count_for(&self, key: &T) -> usize {
+    self.get(key).map_or(0, HashSet::len)
+  }
+
+  /// Gets the stored key and its set if the `key` exists, like `HashMap::get_key_value`.
+  ///
+  /// The index stores its own copy of each key, so the returned `&T` is that stored copy: it is equal to the query key
+  /// but generally a different allocation. This is useful when the stored key is the canonical instance and the
+  /// query key is a temporary.
+  pub fn get_key_value(&self, key: &T) -> Option<(&T, &HashSet<PersonId>)> {
+    let hash = one_shot_128_seeded(&key, self.seed);
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+    self.lookup.find(hash as u64, hash128_equality).map(|(stored_value, set)| (stored_value, set))
+  }
+
   /// Gets a mutable reference to the set associated with the `key` if it exists.
   pub fn get_mut(&mut self, key: &T) -> Option<&mut HashSet<PersonId>> {
-    let hash = one_shot_128(&key);
+    let hash = one_shot_128_seeded(&key, self.seed);
     self.get_with_hash_mut(hash)
   }
 
-  pub fn has_key(&self, key: &T) -> bool {
-    let hash = one_shot_128(&key);
+  /// Gets a mutable reference to the set associated with the given hash if it exists. This is the inherent
+  /// counterpart of `TypeErasedIndex::get_with_hash_mut` for callers that hold the concrete index and a hash computed
+  /// elsewhere, e.g. from a serialized form of the key.
+  ///
+  /// The caller asserts that `hash` is `one_shot_128_seeded(key, self.seed())` for a key of type `T`, e.g. from
+  /// `hash_of_key`.
+  pub fn get_mut_with_hash(&mut self, hash: HashValueType) -> Option<&mut HashSet<PersonId>> {
+    self.get_with_hash_mut(hash)
+  }
+
+  /// Resolves the entry for `key` if it exists. Wrappers in this crate use this to act on a bucket repeatedly, or to
+  /// remove it, without looking it up again.
+  pub(crate) fn occupied_entry(&mut self, key: &T) -> Option<OccupiedEntry<'_, (T, HashSet<PersonId>)>> {
+    let hash = one_shot_128_seeded(&key, self.seed);
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+    self.lookup.find_entry(hash as u64, hash128_equality).ok()
+  }
+
+  /// A handle to the set associated with `key`, resolved once, for repeated access to one bucket without looking it
+  /// up again. Returns `None` if the key is absent. Like `get_mut`, the set stays in the index even if the handle
+  /// empties it.
+  pub fn bucket(&mut self, key: &T) -> Option<BucketRef<'_>> {
+    self.get_mut(key).map(|set| BucketRef { set })
+  }
+
+  /// Gets the entry for `key` for in-place manipulation with a single lookup, like `HashMap::entry`.
+  pub fn entry(&mut self, key: T) -> Entry<'_, T> {
+    let hash = one_shot_128_seeded(&key, self.seed);
+
+    // > hasher is called if entries need to be moved or copied to a new table. This must return the same hash value
+    // > that each entry was inserted with.
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+    match self.lookup.entry(hash as u64, hash128_equality, hasher) {
+      hash_table::Entry::Occupied(entry) => Entry::Occupied(entry),
+      hash_table::Entry::Vacant(entry) => Entry::Vacant(VacantEntry { key, entry }),
+    }
+  }
+
+  /// Does the index contain a set for the given key? Named after `HashMap::contains_key`.
+  pub fn contains_key(&self, key: &T) -> bool {
+    let hash = one_shot_128_seeded(&key, self.seed);
     self.get_with_hash(hash).is_some()
   }
-}
 
+  #[deprecated(note = "use `contains_key`")]
+  pub fn has_key(&self, key: &T) -> bool {
+    self.contains_key(key)
+  }
 
-// This trait Encapsulates the type-erased API.
-pub trait TypeErasedIndex {
-  /// Inserting a new entity only requires the hash but requires the set associated with the hash to already exist.
+  /// Partitions the index into two indexes by a predicate on the keys. Each `(key, set)` entry is moved into the left
+  /// index if `pred(key)` is true and into the right index otherwise. Consumes `self` so that no entity set is cloned.
+  #[must_use]
+  pub fn split_by<F: Fn(&T) -> bool>(self, pred: F) -> (Index<T>, Index<T>) {
+    let mut left = Self::with_seed(self.seed);
+    let mut right = Self::with_seed(self.seed);
+
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    for (key, set) in self.lookup {
+      // Keys are unique in `self`, so they are unique in each half, and we can skip the equality probe.
+      let hash = one_shot_128_seeded(&key, self.seed);
+      let destination = if pred(&key) { &mut left } else { &mut right };
+      destination.lookup.insert_unique(hash as u64, (key, set), hasher);
+    }
+
+    (left, right)
+  }
+
+  /// Removes every entity for which `pred(key, entity_id)` returns true, across all sets, in a single pass. Sets that
+  /// become empty are removed from the index. Returns the removed `(key, entity_id)` pairs in unspecified order,
+  /// cloning the key for each removed entity.
+  pub fn drain_entities<F: FnMut(&T, PersonId) -> bool>(&mut self, mut pred: F) -> Vec<(T, PersonId)> {
+    let mut drained = Vec::new();
+    self.lookup.retain(|(key, set)| {
+      let len_before = drained.len();
+      set.retain(|&entity_id| {
+        if pred(key, entity_id) {
+          drained.push((key.clone(), entity_id));
+          false
+        } else {
+          true
+        }
+      });
+      // Only drop sets that this call emptied.
+      !(set.is_empty() && drained.len() > len_before)
+    });
+    drained
+  }
+
+  /// Keeps only the keys for which `f` returns `true`, e.g. `index.retain(|_, set| !set.is_empty())`. `f` may modify
+  /// each set but only sees the key by shared reference, so stored keys keep the hashes they were inserted with and
+  /// nothing is rehashed.
+  pub fn retain<F: FnMut(&T, &mut HashSet<PersonId>) -> bool>(&mut self, mut f: F) {
+    self.lookup.retain(|(key, set)| f(key, set));
+  }
+
+  /// Restricts the index to the entities in `keep`, removing every other entity from every set. Sets that become
+  /// empty are removed from the index.
+  pub fn retain_entities(&mut self, keep: &HashSet<PersonId>) {
+    self.lookup.retain(|(_, set)| {
+      let was_empty = set.is_empty();
+      set.retain(|entity_id| keep.contains(entity_id));
+      // Only drop sets that this call emptied.
+      was_empty || !set.is_empty()
+    });
+  }
+
+  /// Removes stale entities, i.e. entities not in the simulation's authoritative `live` set, and returns how many
+  /// memberships were pruned. Sets that become empty are removed from the index.
   ///
-  /// If the set corresponding to the hash exists, inserts the `entity_id` into the associated set, returning a `bool`
-  /// according to whether the `entity_id` was already in the set.
-  /// If the set does not exist, returns `Err(())`
-  fn insert_entity_with_hash(&mut self, hash: HashValueType, entity_id: PersonId) -> Result<bool, ()>;
+  /// This is `retain_entities` under the name of its intended use: a periodic garbage-collection pass run after
+  /// entities have left the simulation.
+  pub fn validate_with(&mut self, live: &HashSet<PersonId>) -> usize {
+    let before = self.entity_count();
+    self.retain_entities(live);
+    before - self.entity_count()
+  }
 
-  /// Fetching a set only requires the hash.
-  fn get_with_hash(&self, hash: HashValueType) -> Option<&HashSet<PersonId>>;
+  /// Merges type-erased `(hash, set)` pairs into the index, taking the union of each set with the set of the key that
+  /// produces the same hash.
+  ///
+  /// A typed set cannot be created without a value of `T`, so each hash must belong to a key already in the index.
+  /// The first hash without a matching key is returned as `Err(hash)`. Pairs before it have been merged, and pairs
+  /// after it are not consumed.
+  pub fn merge_hashes<I>(&mut self, iter: I) -> Result<(), HashValueType>
+    where I: IntoIterator<Item = (HashValueType, HashSet<PersonId>)>
+  {
+    for (hash, set) in iter {
+      let existing = self.get_with_hash_mut(hash).ok_or(hash)?;
+      existing.extend(set);
+    }
+    Ok(())
+  }
 
-  /// Fetching a set only requires the hash.
-  fn get_with_hash_mut(&mut self, hash: HashValueType) -> Option<&mut HashSet<PersonId>>;
+  /// Like `merge`, but reports how many keys were created or updated and how many memberships were new, e.g. to check
+  /// that the partitions merged from parallel workers were really disjoint.
+  pub fn merge_reporting(&mut self, other: Index<T>) -> MergeReport {
+    let mut report = MergeReport::default();
+    for (key, set) in other.lookup {
+      match self.entry(key) {
+        Entry::Occupied(mut entry) => {
+          report.keys_updated += 1;
+          let existing = &mut entry.get_mut().1;
+          for entity_id in set {
+            if existing.insert(entity_id) {
+              report.entities_added += 1;
+            } else {
+              report.entities_duplicate += 1;
+            }
+          }
+        }
+        Entry::Vacant(entry) => {
+          report.keys_created += 1;
+          report.entities_added += set.len();
+          entry.insert(set);
+        }
+      }
+    }
+    report
+  }
 
-  /// Does the index contain the given hash?
-  fn has_hash(&self, hash: HashValueType) -> bool;
-}
+  /// Unions each of `other`'s sets into `self`'s set for the same key, ignoring keys that `self` does not contain.
+  /// Unlike `merge`, this never creates keys. Returns the number of keys present in both indexes, i.e. the number of
+  /// sets merged into, whether or not they gained members.
+  pub fn merge_existing(&mut self, other: &Index<T>) -> usize {
+    let mut updated = 0;
+    for (key, set) in other.lookup.iter() {
+      if let Some(existing) = self.get_mut(key) {
+        existing.extend(set);
+        updated += 1;
+      }
+    }
+    updated
+  }
 
+  /// Renumbers every member of every set through `mapping`, e.g. to compact a sparse id space. Ids missing from
+  /// `mapping` are kept or removed according to `unmapped`, and a set left empty by removal is dropped from the index.
+  /// If two members of a set map to the same id, they become one member.
+  pub fn remap_ids(&mut self, mapping: &HashMap<PersonId, PersonId>, unmapped: UnmappedIds) {
+    let mut scratch = Vec::new();
+    self.lookup.retain(|(_, set)| {
+      let was_empty = set.is_empty();
+      remap_set(set, mapping, unmapped, &mut scratch);
+      // Only drop sets that this call emptied.
+      was_empty || !set.is_empty()
+    });
+  }
 
-impl<T: Hash + Eq + Clone + Any> TypeErasedIndex for Index<T> {
-  /// Inserting a new entity only requires the hash but requires the set associated with the hash to already exist.
+  /// A key whose set contains `entity_id`, or `None` if no set does. For an index whose sets partition the
+  /// population this is the entity's only key; otherwise which of its keys is returned is unspecified.
   ///
-  /// If the set corresponding to the hash exists, inserts the `entity_id` into the associated set, returning a `bool`
-  /// according to whether the `entity_id` was already in the set.
-  /// If the set does not exist, returns `Err(())`
-  fn insert_entity_with_hash(&mut self, hash: HashValueType, entity_id: PersonId) -> Result<bool, ()> {
-    // Equality is determined by comparing the full 128-bit hashes. We do not expect any collisions before the heat
-    // death of the universe.
-    let hash128_equality = |(stored_value, _): &_| one_shot_128(stored_value) == hash;
+  /// The index keeps no reverse map from entities to keys, so this scans the sets and is O(n) in the number of keys.
+  pub fn key_for_entity(&self, entity_id: PersonId) -> Option<&T> {
+    self.lookup.iter().find(|(_, set)| set.contains(&entity_id)).map(|(key, _)| key)
+  }
 
-    let entities = self.lookup.find_mut(hash as u64, hash128_equality).map(|(_, set)| set).ok_or(())?;
-    Ok(entities.insert(entity_id))
+  /// All keys whose sets contain `entity_id`, in unspecified order. Like `key_for_entity`, this is O(n) in the number
+  /// of keys.
+  pub fn keys_for_entity(&self, entity_id: PersonId) -> Vec<&T> {
+    self.lookup.iter().filter(|(_, set)| set.contains(&entity_id)).map(|(key, _)| key).collect()
   }
 
-  /// Fetching a set only requires the hash.
-  fn get_with_hash(&self, hash: HashValueType) -> Option<&HashSet<PersonId>> {
-    // Equality is determined by comparing the full 128-bit hashes. We do not expect any collisions before the heat
-    // death of the universe.
-    let hash128_equality = |(stored_value, _): &_| one_shot_128(stored_value) == hash;
-    self.lookup.find(hash as u64, hash128_equality).map(|(_, set)| set)
+  /// Tests each of `ids` for membership in the set associated with `key`, resolving the set once. The result is
+  /// aligned with `ids`, and is all `false` if the key is absent.
+  pub fn bulk_contains(&self, key: &T, ids: &[PersonId]) -> Vec<bool> {
+    match self.get(key) {
+      Some(set) => ids.iter().map(|entity_id| set.contains(entity_id)).collect(),
+      None => vec![false; ids.len()],
+    }
   }
 
-  /// Fetching a set only requires the hash.
-  fn get_with_hash_mut(&mut self, hash: HashValueType) -> Option<&mut HashSet<PersonId>> {
-    // Equality is determined by comparing the full 128-bit hashes. We do not expect any collisions before the heat
-    // death of the universe.
-    let hash128_equality = |(stored_value, _): &_| one_shot_128(stored_value) == hash;
-    self.lookup.find_mut(hash as u64, hash128_equality).map(|(_, set)| set)
+  /// Extends `dst` with the members of the set associated with `key`, or leaves it unchanged if the key is absent.
+  /// Lets a running union over many keys reuse one destination set.
+  pub fn union_into(&self, key: &T, dst: &mut HashSet<PersonId>) {
+    if let Some(set) = self.get(key) {
+      dst.extend(set);
+    }
   }
 
-  fn has_hash(&self, hash: HashValueType) -> bool {
-    self.get_with_hash(hash).is_some()
+  /// Replaces every key with `f(key)`, e.g. when migrating a categorical encoding. Keys that `f` maps to the same new
+  /// key have their sets merged. The sets themselves are moved, not cloned.
+  pub fn rename_keys<F: Fn(&T) -> T>(&mut self, f: F) {
+    let capacity = self.lookup.len();
+    let old = std::mem::replace(&mut self.lookup, HashTable::with_capacity(capacity));
+    for (key, set) in old {
+      match self.entry(f(&key)) {
+        Entry::Occupied(mut entry) => entry.get_mut().1.extend(set),
+        Entry::Vacant(entry) => { entry.insert(set); }
+      }
+    }
+  }
+
+  /// The pairwise intersection sizes of all of the index's sets: returns the keys and a matrix whose entry `[i][j]` is
+  /// the number of entities in the sets of both `keys[i]` and `keys[j]`. The diagonal holds the set sizes. Keys are in
+  /// unspecified order.
+  ///
+  /// The index is inverted into an entity-to-keys map once, and then each entity's keys are counted pairwise. The cost
+  /// is O(Σ k²) over all entities, where k is the number of sets the entity belongs to, plus O(n²) for the matrix
+  /// itself. For an index whose sets partition the population the matrix is diagonal.
+  pub fn overlap_matrix(&self) -> (Vec<T>, Vec<Vec<usize>>) {
+    let keys: Vec<T> = self.lookup.iter().map(|(key, _)| key.clone()).collect();
+    let mut key_positions_by_entity: HashMap<PersonId, Vec<usize>> = HashMap::new();
+    for (position, (_, set)) in self.lookup.iter().enumerate() {
+      for &entity_id in set {
+        key_positions_by_entity.entry(entity_id).or_default().push(position);
+      }
+    }
+
+    let mut matrix = vec![vec![0; keys.len()]; keys.len()];
+    for positions in key_positions_by_entity.values() {
+      for &i in positions {
+        for &j in positions {
+          matrix[i][j] += 1;
+        }
+      }
+    }
+    (keys, matrix)
+  }
+
+  /// Iterates over the `(key, set)` pairs in unspecified order.
+  pub fn iter(&self) -> impl Iterator<Item = (&T, &HashSet<PersonId>)> {
+    self.lookup.iter().map(|(key, set)| (key, set))
+  }
+
+  /// Iterates over the keys, i.e. the distinct property values, in unspecified order. Does not allocate.
+  pub fn iter_keys(&self) -> impl Iterator<Item = &T> {
+    self.lookup.iter().map(|(key, _)| key)
+  }
+
+  /// An owned copy of every key, for callers that need owned values. Allocates and clones every key. The order is
+  /// unspecified; sort the result if you need a stable one.
+  pub fn keys(&self) -> Vec<T> {
+    self.iter_keys().cloned().collect()
+  }
+
+  /// The same as `keys`, for consumers that cannot take an iterator, e.g. across an FFI boundary. Named to pair with
+  /// `entities_vec`.
+  pub fn keys_vec(&self) -> Vec<T> {
+    self.keys()
+  }
+
+  /// An owned copy of the members of the set associated with `key`, or `None` if the key is absent. Like `keys_vec`,
+  /// this allocates, and the order is unspecified.
+  pub fn entities_vec(&self, key: &T) -> Option<Vec<PersonId>> {
+    self.get(key).map(|set| set.iter().copied().collect())
+  }
+
+  /// Expands the index into two parallel columns of `(value, entity)` pairs, the shape columnar formats such as Arrow
+  /// expect. A key is repeated once per member of its set, so both columns have length `entity_count()`. The order of
+  /// the rows is unspecified; sort them if you need a stable order.
+  pub fn to_columns(&self) -> (Vec<T>, Vec<PersonId>) {
+    let row_count = self.entity_count();
+    let mut keys = Vec::with_capacity(row_count);
+    let mut entities = Vec::with_capacity(row_count);
+    for (key, set) in self.lookup.iter() {
+      for &entity_id in set {
+        keys.push(key.clone());
+        entities.push(entity_id);
+      }
+    }
+    (keys, entities)
+  }
+
+  /// Iterates over every entity in the index once, even if it belongs to several sets, in unspecified order. The
+  /// iterator records the entities it has yielded, so it holds O(n) memory in the number of distinct entities.
+  pub fn unique_entities(&self) -> impl Iterator<Item = PersonId> + '_ {
+    let mut seen = HashSet::new();
+    self.lookup
+        .iter()
+        .flat_map(|(_, set)| set.iter().copied())
+        .filter(move |entity_id| seen.insert(*entity_id))
+  }
+
+  /// Counts the keys whose `(key, set)` pair satisfies `pred`.
+  pub fn keys_matching<F: Fn(&T, &HashSet<PersonId>) -> bool>(&self, pred: F) -> usize {
+    self.lookup.iter().filter(|(key, set)| pred(key, set)).count()
+  }
+
+  /// Sums the set sizes of the keys whose `(key, set)` pair satisfies `pred`.
+  pub fn entities_matching<F: Fn(&T, &HashSet<PersonId>) -> bool>(&self, pred: F) -> usize {
+    self.lookup.iter().filter(|(key, set)| pred(key, set)).map(|(_, set)| set.len()).sum()
+  }
+
+  /// Returns true if no entity belongs to more than one of the buckets of `keys`, e.g. to check that mutually
+  /// exclusive categories really are. Absent keys count as empty buckets, and a key repeated in `keys` is one bucket.
+  ///
+  /// The buckets are scanned from smallest to largest while tracking the entities seen so far, and the scan stops at
+  /// the first overlap.
+  pub fn are_disjoint(&self, keys: &[T]) -> bool {
+    let mut hashes = HashSet::with_capacity(keys.len());
+    let mut buckets: Vec<&HashSet<PersonId>> = keys
+        .iter()
+        .map(|key| one_shot_128_seeded(key, self.seed))
+        .filter(|hash| hashes.insert(*hash))
+        .filter_map(|hash| self.get_with_hash(hash))
+        .collect();
+    buckets.sort_unstable_by_key(|set| set.len());
+
+    let mut seen = HashSet::new();
+    buckets.iter().all(|set| set.iter().all(|entity_id| seen.insert(*entity_id)))
+  }
+
+  /// The sizes of all sets in the index in ascending order, one per key, for computing cohort-size quantiles.
+  pub fn bucket_sizes_sorted(&self) -> Vec<usize> {
+    let mut sizes: Vec<usize> = self.lookup.iter().map(|(_, set)| set.len()).collect();
+    sizes.sort_unstable();
+    sizes
+  }
+
+  /// Splits the keys by the size of their sets: returns the number of sets with at most `threshold` members and the
+  /// number with more.
+  pub fn size_threshold_report(&self, threshold: usize) -> (usize, usize) {
+    let at_or_below = self.lookup.iter().filter(|(_, set)| set.len() <= threshold).count();
+    (at_or_below, self.lookup.len() - at_or_below)
+  }
+
+  /// Every pair of distinct stored keys with the same 128-bit hash. Equality by hash assumes there are none, so for a
+  /// correctly distributed dataset this is empty.
+  ///
+  /// Only keys stored under separate entries can be reported. A key inserted through `insert_entity` whose hash
+  /// collides with an existing key is merged into that key's set, so this diagnostic catches collisions among keys
+  /// loaded with `insert_value`; to check a dataset before loading it, compare `one_shot_128` of its distinct keys.
+  pub fn full_hash_collisions(&self) -> Vec<(T, T)> {
+    let mut keys_by_hash: HashMap<HashValueType, Vec<&T>> = HashMap::new();
+    for (key, _) in self.lookup.iter() {
+      keys_by_hash.entry(one_shot_128_seeded(key, self.seed)).or_default().push(key);
+    }
+
+    let mut collisions = Vec::new();
+    for keys in keys_by_hash.values().filter(|keys| keys.len() > 1) {
+      for (i, first) in keys.iter().enumerate() {
+        for second in &keys[i + 1..] {
+          if first != second {
+            collisions.push(((*first).clone(), (*second).clone()));
+          }
+        }
+      }
+    }
+    collisions
+  }
+
+  /// A digest of the index's full contents. Indexes with the same keys and sets have the same fingerprint regardless
+  /// of insertion order, and a typed index has the same fingerprint as a type-erased index holding the same hashes.
+  pub fn fingerprint(&self) -> HashValueType {
+    index_fingerprint(self.lookup.iter().map(|(key, set)| (one_shot_128_seeded(key, self.seed), set)))
+  }
+
+  /// Uniformly samples one member of the set associated with `key`, or `None` if the key is absent or its set is
+  /// empty. A `HashSet` has no random access, so this walks the set and is O(n) in its size.
+  #[cfg(feature = "rand")]
+  pub fn sample_entity<R: rand::Rng + ?Sized>(&self, key: &T, rng: &mut R) -> Option<PersonId> {
+    use rand::seq::IteratorRandom;
+    self.get(key)?.iter().copied().choose(rng)
+  }
+
+  /// Samples `amount` distinct members of the set associated with `key`, without replacement. Returns fewer if the set
+  /// is smaller than `amount`, and none if the key is absent. Like `sample_entity`, this is O(n) in the set's size.
+  /// The order of the returned entities is not random.
+  #[cfg(feature = "rand")]
+  pub fn sample_entities<R: rand::Rng + ?Sized>(&self, key: &T, amount: usize, rng: &mut R) -> Vec<PersonId> {
+    use rand::seq::IteratorRandom;
+    match self.get(key) {
+      Some(set) => set.iter().copied().choose_multiple(rng, amount),
+      None => Vec::new(),
+    }
+  }
+
+  /// Makes `dst` a copy of `self`, like `dst.clone_from(self)`, reusing `dst`'s allocations. The table itself is
+  /// kept, the sets of keys present in both indexes are overwritten in place, and the sets of keys only in `dst` are
+  /// recycled for keys only in `self`. Copying between indexes with mostly the same keys therefore allocates little.
+  pub fn clone_into(&self, dst: &mut Index<T>) {
+    if dst.seed != self.seed {
+      // `dst`'s entries are placed by hashes under its own seed, so its table cannot be reused.
+      *dst = self.clone();
+      return;
+    }
+    let mut spare_sets: Vec<HashSet<PersonId>> = dst.lookup
+        .extract_if(|(key, _)| !self.contains_key(key))
+        .map(|(_, set)| set)
+        .collect();
+    for (key, set) in dst.lookup.iter_mut() {
+      if let Some(source) = self.get(key) {
+        set.clone_from(source);
+      }
+    }
+    if dst.lookup.len() == self.lookup.len() {
+      return;
+    }
+
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    dst.lookup.reserve(self.lookup.len() - dst.lookup.len(), hasher);
+    for (key, source) in self.lookup.iter() {
+      let hash = one_shot_128_seeded(key, self.seed);
+      if dst.contains_hash(hash) {
+        continue;
+      }
+      let mut set = spare_sets.pop().unwrap_or_default();
+      set.clone_from(source);
+      dst.lookup.insert_unique(hash as u64, (key.clone(), set), hasher);
+    }
+  }
+
+  /// Packs the index into an immutable `FrozenIndex<T>` optimized for lookups. Use `FrozenIndex::thaw` to get a
+  /// mutable index back.
+  pub fn freeze(self) -> FrozenIndex<T> {
+    FrozenIndex::from_entries(self.lookup, self.seed)
+  }
+
+  /// A structured comparison of `self` with `other`, for reporting how two indexes differ, e.g. when a regression
+  /// test fails. Additions and removals are relative to `self`: `added` holds the members only `other` has.
+  pub fn diff(&self, other: &Index<T>) -> IndexDiff<T> {
+    let mut diff = IndexDiff {
+      only_in_self: Vec::new(),
+      only_in_other: Vec::new(),
+      changed: Vec::new(),
+    };
+    for (key, set) in self.lookup.iter() {
+      let Some(other_set) = other.get(key) else {
+        diff.only_in_self.push(key.clone());
+        continue;
+      };
+      if set == other_set {
+        continue;
+      }
+      let mut added: Vec<PersonId> = other_set.difference(set).copied().collect();
+      let mut removed: Vec<PersonId> = set.difference(other_set).copied().collect();
+      added.sort_unstable();
+      removed.sort_unstable();
+      diff.changed.push(KeyDiff { key: key.clone(), added, removed });
+    }
+    diff.only_in_other = other.lookup
+        .iter()
+        .filter(|(key, _)| !self.contains_key(key))
+        .map(|(key, _)| key.clone())
+        .collect();
+    diff
+  }
+
+  /// Converts into a fully type-erased index with the same hashes, seed and sets, dropping the stored keys to save
+  /// their memory. This is one way: the keys cannot be recovered afterwards, so the result can be queried by key or hash
+  /// but not iterated by key. Suited to workloads that load once and then only query.
+  ///
+  /// `insert_value` does not check for an existing key, so the index may hold several entries with the same hash.
+  /// Their sets are merged, since the type-erased index keeps one set per hash.
+  pub fn forget_keys(self) -> type_erased_index::Index {
+    let mut erased = type_erased_index::Index::with_capacity_and_seed(self.lookup.len(), self.seed);
+    for (key, set) in self.lookup {
+      let hash = one_shot_128_seeded(&key, self.seed);
+      match erased.get_with_hash_mut(hash) {
+        Some(existing) => existing.extend(set),
+        None => { erased.insert_with_hash(hash, set); }
+      }
+    }
+    erased
+  }
+
+  /// A read-only view of the index with a `HashMap`-like interface.
+  pub fn as_map(&self) -> IndexMapView<'_, T> {
+    IndexMapView { index: self }
+  }
+
+  /// Merges `other` into `self`, taking the union of the sets of keys present in both indexes.
+  ///
+  /// The destination table is reserved for all of `other`'s keys up front, and each shared key's set is reserved for
+  /// the incoming members before it is extended, so the merge does not repeatedly grow the destination.
+  pub fn merge(&mut self, other: Index<T>) {
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    // This over-reserves when keys are shared, which is cheaper than growing the table several times mid-merge.
+    self.lookup.reserve(other.lookup.len(), hasher);
+
+    for (key, set) in other.lookup {
+      let hash = one_shot_128_seeded(&key, self.seed);
+      let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+      match self.lookup.entry(hash as u64, hash128_equality, hasher) {
+        hash_table::Entry::Occupied(mut entry) => {
+          let existing = &mut entry.get_mut().1;
+          existing.reserve(set.len());
+          existing.extend(set);
+        }
+        hash_table::Entry::Vacant(entry) => {
+          entry.insert((key, set));
+        }
+      }
+    }
+  }
+}
+
+/// Parallel iteration, for computing per-bucket statistics across cores.
+#[cfg(feature = "rayon")]
+impl<T: Hash + Eq + Clone + Any + Send + Sync> Index<T> {
+  /// Iterates over the `(key, set)` pairs in parallel, borrowing them from the index.
+  pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&T, &HashSet<PersonId>)> {
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+    (&self.lookup).into_par_iter().map(|(key, set)| (key, set))
+  }
+
+  /// Like `par_iter`, but clones each `(key, set)` pair so that consumers receive owned items.
+  pub fn par_iter_owned(&self) -> impl rayon::iter::ParallelIterator<Item = (T, HashSet<PersonId>)> {
+    use rayon::iter::ParallelIterator;
+    self.par_iter().map(|(key, set)| (key.clone(), set.clone()))
+  }
+}
+
+/// The differences between two indexes, as computed by `Index::diff`. Keys are in unspecified order; entity lists are
+/// sorted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexDiff<T> {
+  /// Keys present only in the index `diff` was called on.
+  pub only_in_self: Vec<T>,
+  /// Keys present only in the index passed to `diff`.
+  pub only_in_other: Vec<T>,
+  /// Keys present in both whose sets differ.
+  pub changed: Vec<KeyDiff<T>>,
+}
+
+/// How the set of one key differs between two indexes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDiff<T> {
+  pub key: T,
+  /// Members only in the other index.
+  pub added: Vec<PersonId>,
+  /// Members only in the index `diff` was called on.
+  pub removed: Vec<PersonId>,
+}
+
+impl<T> IndexDiff<T> {
+  /// Whether the two indexes had the same keys and sets.
+  pub fn is_empty(&self) -> bool {
+    self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.changed.is_empty()
+  }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Display for IndexDiff<T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if self.is_empty() {
+      return writeln!(f, "indexes are identical");
+    }
+    for key in &self.only_in_self {
+      writeln!(f, "- {:?}", key)?;
+    }
+    for key in &self.only_in_other {
+      writeln!(f, "+ {:?}", key)?;
+    }
+    for KeyDiff { key, added, removed } in &self.changed {
+      writeln!(f, "~ {:?}: added {:?}, removed {:?}", key, added, removed)?;
+    }
+    Ok(())
+  }
+}
+
+/// A read-only, map-shaped view of an `Index<T>`, behaving like a `&HashMap<T, HashSet<PersonId>>` for code written
+/// against map interfaces. It borrows the index and copies nothing.
+#[derive(Debug)]
+pub struct IndexMapView<'a, T: Hash + Eq + Clone + Any> {
+  index: &'a Index<T>,
+}
+
+// Not derived, because the derives would require `T: Copy`.
+impl<T: Hash + Eq + Clone + Any> Clone for IndexMapView<'_, T> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<T: Hash + Eq + Clone + Any> Copy for IndexMapView<'_, T> {}
+
+impl<'a, T: Hash + Eq + Clone + Any> IndexMapView<'a, T> {
+  pub fn get(&self, key: &T) -> Option<&'a HashSet<PersonId>> {
+    self.index.get(key)
+  }
+
+  pub fn contains_key(&self, key: &T) -> bool {
+    self.index.contains_key(key)
+  }
+
+  /// The number of keys.
+  pub fn len(&self) -> usize {
+    self.index.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.index.is_empty()
+  }
+
+  /// Iterates over the `(key, set)` pairs in unspecified order.
+  pub fn iter(&self) -> impl Iterator<Item = (&'a T, &'a HashSet<PersonId>)> + 'a {
+    self.index.iter()
+  }
+}
+
+/// A resolved bucket of an `Index<T>`, returned by `Index::bucket`.
+#[derive(Debug)]
+pub struct BucketRef<'a> {
+  set: &'a mut HashSet<PersonId>,
+}
+
+impl BucketRef<'_> {
+  /// Adds `entity_id` to the bucket, returning whether it was newly added.
+  pub fn insert(&mut self, entity_id: PersonId) -> bool {
+    self.set.insert(entity_id)
+  }
+
+  /// Removes `entity_id` from the bucket, returning whether it was present.
+  pub fn remove(&mut self, entity_id: PersonId) -> bool {
+    self.set.remove(&entity_id)
+  }
+
+  pub fn contains(&self, entity_id: PersonId) -> bool {
+    self.set.contains(&entity_id)
+  }
+
+  pub fn len(&self) -> usize {
+    self.set.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.set.is_empty()
+  }
+}
+
+/// A view into a single bucket of an `Index<T>`, which may be occupied or vacant. Returned by `Index::entry` and
+/// modeled on `std::collections::hash_map::Entry`.
+pub enum Entry<'a, T: Hash + Eq + Clone + Any> {
+  Occupied(OccupiedEntry<'a, (T, HashSet<PersonId>)>),
+  Vacant(VacantEntry<'a, T>),
+}
+
+/// A vacant bucket of an `Index<T>`, holding the key that will be stored if a set is inserted.
+pub struct VacantEntry<'a, T: Hash + Eq + Clone + Any> {
+  key: T,
+  entry: hash_table::VacantEntry<'a, (T, HashSet<PersonId>)>,
+}
+
+impl<'a, T: Hash + Eq + Clone + Any> VacantEntry<'a, T> {
+  pub fn key(&self) -> &T {
+    &self.key
+  }
+
+  /// Stores `set` as the key's set and returns a mutable reference to it.
+  pub fn insert(self, set: HashSet<PersonId>) -> &'a mut HashSet<PersonId> {
+    &mut self.entry.insert((self.key, set)).into_mut().1
+  }
+}
+
+impl<'a, T: Hash + Eq + Clone + Any> Entry<'a, T> {
+  pub fn key(&self) -> &T {
+    match self {
+      Entry::Occupied(entry) => &entry.get().0,
+      Entry::Vacant(entry) => entry.key(),
+    }
+  }
+
+  /// Inserts `set` if the entry is vacant. Returns a mutable reference to the key's set either way.
+  pub fn or_insert(self, set: HashSet<PersonId>) -> &'a mut HashSet<PersonId> {
+    self.or_insert_with(|| set)
+  }
+
+  /// Inserts the set returned by `default` if the entry is vacant. Returns a mutable reference to the key's set
+  /// either way.
+  pub fn or_insert_with<F: FnOnce() -> HashSet<PersonId>>(self, default: F) -> &'a mut HashSet<PersonId> {
+    match self {
+      Entry::Occupied(entry) => &mut entry.into_mut().1,
+      Entry::Vacant(entry) => entry.insert(default()),
+    }
+  }
+
+  /// Inserts an empty set if the entry is vacant. Returns a mutable reference to the key's set either way.
+  pub fn or_default(self) -> &'a mut HashSet<PersonId> {
+    self.or_insert_with(HashSet::new)
+  }
+
+  /// Applies `f` to the key's set if the entry is occupied, and returns the entry for further chaining.
+  pub fn and_modify<F: FnOnce(&mut HashSet<PersonId>)>(self, f: F) -> Self {
+    match self {
+      Entry::Occupied(mut entry) => {
+        f(&mut entry.get_mut().1);
+        Entry::Occupied(entry)
+      }
+      Entry::Vacant(entry) => Entry::Vacant(entry),
+    }
+  }
+}
+
+/// Queries that need ordered keys.
+impl<T: Hash + Eq + Clone + Any + Ord> Index<T> {
+  /// The smallest key in the index, found in a single pass without sorting. `None` if the index is empty.
+  pub fn min_key(&self) -> Option<&T> {
+    self.lookup.iter().map(|(key, _)| key).min()
+  }
+
+  /// The largest key in the index, found in a single pass without sorting. `None` if the index is empty.
+  pub fn max_key(&self) -> Option<&T> {
+    self.lookup.iter().map(|(key, _)| key).max()
+  }
+}
+
+/// The size of the intersection of every pair of sets across two indexes, keyed by `(a_key, b_key)`: the cells of a
+/// contingency table of the two properties. Every pair of keys has an entry, including pairs whose sets are disjoint.
+///
+/// No intersection sets are materialized. Each count walks the smaller of the two sets and probes the larger, so the
+/// total cost is O(Σ min(|A_i|, |B_j|)) over all key pairs. That is at most O(K_b·|A|), where K_b is the number of
+/// keys in `b` and |A| is `a.entity_count()`, and symmetrically for `b`.
+pub fn intersection_counts<T, U>(a: &Index<T>, b: &Index<U>) -> HashMap<(T, U), usize>
+  where T: Hash + Eq + Clone + Any,
+        U: Hash + Eq + Clone + Any
+{
+  // Not reserved up front: the cross product of the key counts can be far larger than either index.
+  let mut counts = HashMap::new();
+  for (a_key, a_set) in a.lookup.iter() {
+    for (b_key, b_set) in b.lookup.iter() {
+      let (smaller, larger) = if a_set.len() <= b_set.len() { (a_set, b_set) } else { (b_set, a_set) };
+      let count = smaller.iter().filter(|entity_id| larger.contains(entity_id)).count();
+      counts.insert((a_key.clone(), b_key.clone()), count);
+    }
+  }
+  counts
+}
+
+
+// This trait Encapsulates the type-erased API.
+pub trait TypeErasedIndex {
+  /// Inserting a new entity only requires the hash but requires the set associated with the hash to already exist.
+  ///
+  /// If the set corresponding to the hash exists, inserts the `entity_id` into the associated set, returning a `bool`
+  /// according to whether the `entity_id` was already in the set.
+  /// If the set does not exist, returns `Err(())`
+  #[allow(clippy::result_unit_err)]
+  fn insert_entity_with_hash(&mut self, hash: HashValueType, entity_id: PersonId) -> Result<bool, ()>;
+
+  /// Fetching a set only requires the hash.
+  fn get_with_hash(&self, hash: HashValueType) -> Option<&HashSet<PersonId>>;
+
+  /// Fetching a set only requires the hash.
+  fn get_with_hash_mut(&mut self, hash: HashValueType) -> Option<&mut HashSet<PersonId>>;
+
+  /// Does the index contain the given hash?
+  fn contains_hash(&self, hash: HashValueType) -> bool;
+
+  #[deprecated(note = "use `contains_hash`")]
+  fn has_hash(&self, hash: HashValueType) -> bool {
+    self.contains_hash(hash)
+  }
+
+  /// The sum of the sizes of all sets in the index, i.e. an entity is counted once for every set it belongs to. For
+  /// an index of a property whose values partition the population, this is the population size.
+  fn entity_count(&self) -> usize;
+
+  /// The number of sets that contain `entity_id`. This is 0 or 1 for an index whose sets partition the population.
+  /// The index keeps no reverse map, so this scans the sets and is O(n) in the number of keys.
+  fn entity_degree(&self, entity_id: PersonId) -> usize;
+
+  /// An estimate of the heap memory held by the index, in bytes: the table's slots plus the slots of every set. It
+  /// ignores per-allocation overhead, hash table control bytes, and memory owned by the keys themselves.
+  fn approx_memory_bytes(&self) -> usize;
+
+  /// Removes every key and set, and makes room for at least `capacity` keys, keeping the existing table if it is
+  /// large enough. Used to reset an index between runs without growing it again from scratch.
+  fn clear_and_reserve(&mut self, capacity: usize);
+
+  /// Clones the index into a new box, since `Clone` itself cannot be called through a trait object.
+  fn box_clone(&self) -> BxIndex;
+
+  /// The `TypeId` of the index's key type, i.e. `TypeId::of::<T>()` for an `Index<T>`.
+  fn value_type_id(&self) -> TypeId;
+
+  /// The seed the index hashes its keys with, 0 unless it was created with `Index::with_seed`. Hashes passed to the
+  /// methods above must be computed with `one_shot_128_seeded(key, seed)`.
+  fn seed(&self) -> u64;
+
+  /// Converts the boxed index into `Box<dyn Any>`, so that it can be downcast to its concrete type.
+  fn into_any(self: Box<Self>) -> Box<dyn Any>;
+
+  /// Merges `other` into `self`, taking the union of the sets of keys present in both, if both indexes have the same
+  /// key type. Otherwise returns `other` unchanged.
+  fn merge_boxed(&mut self, other: BxIndex) -> Result<(), BxIndex>;
+}
+
+
+impl<T: Hash + Eq + Clone + Any> TypeErasedIndex for Index<T> {
+  /// Inserting a new entity only requires the hash but requires the set associated with the hash to already exist.
+  ///
+  /// If the set corresponding to the hash exists, inserts the `entity_id` into the associated set, returning a `bool`
+  /// according to whether the `entity_id` was already in the set.
+  /// If the set does not exist, returns `Err(())`
+  fn insert_entity_with_hash(&mut self, hash: HashValueType, entity_id: PersonId) -> Result<bool, ()> {
+    // Equality is determined by comparing the full 128-bit hashes. We do not expect any collisions before the heat
+    // death of the universe.
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+
+    let entities = self.lookup.find_mut(hash as u64, hash128_equality).map(|(_, set)| set).ok_or(())?;
+    Ok(entities.insert(entity_id))
+  }
+
+  /// Fetching a set only requires the hash.
+  fn get_with_hash(&self, hash: HashValueType) -> Option<&HashSet<PersonId>> {
+    // Equality is determined by comparing the full 128-bit hashes. We do not expect any collisions before the heat
+    // death of the universe.
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+    self.lookup.find(hash as u64, hash128_equality).map(|(_, set)| set)
+  }
+
+  /// Fetching a set only requires the hash.
+  fn get_with_hash_mut(&mut self, hash: HashValueType) -> Option<&mut HashSet<PersonId>> {
+    // Equality is determined by comparing the full 128-bit hashes. We do not expect any collisions before the heat
+    // death of the universe.
+    let hash128_equality = |(stored_value, _): &_| one_shot_128_seeded(stored_value, self.seed) == hash;
+    self.lookup.find_mut(hash as u64, hash128_equality).map(|(_, set)| set)
+  }
+
+  fn contains_hash(&self, hash: HashValueType) -> bool {
+    self.get_with_hash(hash).is_some()
+  }
+
+  fn entity_count(&self) -> usize {
+    self.lookup.iter().map(|(_, set)| set.len()).sum()
+  }
+
+  fn entity_degree(&self, entity_id: PersonId) -> usize {
+    self.lookup.iter().filter(|(_, set)| set.contains(&entity_id)).count()
+  }
+
+  fn approx_memory_bytes(&self) -> usize {
+    let table_bytes = self.lookup.capacity() * size_of::<(T, HashSet<PersonId>)>();
+    let set_bytes: usize = self.lookup.iter().map(|(_, set)| set.capacity() * size_of::<PersonId>()).sum();
+    table_bytes + set_bytes
+  }
+
+  fn clear_and_reserve(&mut self, capacity: usize) {
+    self.clear();
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128_seeded(stored_value, self.seed) as u64;
+    self.lookup.reserve(capacity, hasher);
+  }
+
+  fn box_clone(&self) -> BxIndex {
+    Box::new(self.clone())
+  }
+
+  fn value_type_id(&self) -> TypeId {
+    TypeId::of::<T>()
+  }
+
+  fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  fn into_any(self: Box<Self>) -> Box<dyn Any> {
+    self
+  }
+
+  fn merge_boxed(&mut self, other: BxIndex) -> Result<(), BxIndex> {
+    if other.value_type_id() != self.value_type_id() {
+      return Err(other);
+    }
+    let other = other.into_any().downcast::<Index<T>>().expect("key types were checked to be equal");
+    self.merge(*other);
+    Ok(())
+  }
+}
+
+/// Generates a random index state from random `(T, HashSet<PersonId>)` pairs. Generated keys may repeat, in which
+/// case their sets are merged.
+#[cfg(feature = "fuzz")]
+impl<'a, T> arbitrary::Arbitrary<'a> for Index<T>
+  where T: Hash + Eq + Clone + Any + arbitrary::Arbitrary<'a>
+{
+  fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+    let entries: Vec<(T, HashSet<PersonId>)> = u.arbitrary()?;
+    let mut index = Self::new();
+    for (key, set) in entries {
+      // `insert_value` assumes the key is new, so duplicate keys have to be merged into the existing set.
+      match index.get_mut(&key) {
+        Some(existing) => existing.extend(set),
+        None => { index.insert_value(key, set); }
+      }
+    }
+    Ok(index)
+  }
+}
+
+/// Serializes as the seed followed by a sequence of `(key, members)` pairs, with each bucket's members in ascending
+/// order.
+#[cfg(feature = "serde")]
+impl<T> serde::Serialize for Index<T>
+  where T: Hash + Eq + Clone + Any + serde::Serialize
+{
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let entries: Vec<(&T, Vec<PersonId>)> = self.lookup
+        .iter()
+        .map(|(key, set)| {
+          let mut members: Vec<PersonId> = set.iter().copied().collect();
+          members.sort_unstable();
+          (key, members)
+        })
+        .collect();
+    serde::Serialize::serialize(&(self.seed, entries), serializer)
+  }
+}
+
+/// Deserializes the form written by `Serialize` into an index with the recorded seed. Keys that appear more than once
+/// have their members merged.
+#[cfg(feature = "serde")]
+impl<'de, T> serde::Deserialize<'de> for Index<T>
+  where T: Hash + Eq + Clone + Any + serde::de::DeserializeOwned
+{
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let (seed, entries): (u64, Vec<(T, Vec<PersonId>)>) = serde::Deserialize::deserialize(deserializer)?;
+    let mut index = Self::with_seed(seed);
+    for (key, members) in entries {
+      // `insert_value` assumes the key is new, which untrusted input need not respect.
+      index.entry(key).or_default().extend(members);
+    }
+    Ok(index)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::hash128::one_shot_128;
+
+  #[test]
+  fn insert_entity_with_size_reports_bucket_len() {
+    let mut index: Index<&'static str> = Index::new();
+
+    assert_eq!(index.insert_entity_with_size(&"infected", 1), (true, 1));
+    assert_eq!(index.insert_entity_with_size(&"infected", 2), (true, 2));
+    // A duplicate insert does not grow the set.
+    assert_eq!(index.insert_entity_with_size(&"infected", 2), (false, 2));
+    assert_eq!(index.insert_entity_with_size(&"recovered", 2), (true, 1));
+  }
+
+  #[test]
+  fn insert_entity_cow_accepts_owned_and_borrowed_keys() {
+    let mut index: Index<String> = Index::new();
+    let key = "susceptible".to_string();
+
+    assert!(index.insert_entity_cow(Cow::Borrowed(&key), 1));
+    assert!(index.insert_entity_cow(Cow::Owned("susceptible".to_string()), 2));
+    assert!(!index.insert_entity_cow(Cow::Owned(key.clone()), 2));
+    assert!(index.insert_entity_cow(Cow::Owned("exposed".to_string()), 3));
+
+    assert_eq!(index.get(&key), Some(&HashSet::from([1, 2])));
+    assert_eq!(index.get(&"exposed".to_string()), Some(&HashSet::from([3])));
+  }
+
+  #[test]
+  fn contains_key_and_hash() {
+    let mut index: Index<u32> = Index::new();
+    let _ = index.insert_entity(&7, 1);
+
+    assert!(index.contains_key(&7));
+    assert!(!index.contains_key(&8));
+    assert!(index.contains_hash(one_shot_128(&7u32)));
+    assert!(!index.contains_hash(one_shot_128(&8u32)));
+  }
+
+  #[test]
+  fn insert_entity_change_variants() {
+    let mut index: Index<u32> = Index::new();
+    assert_eq!(index.insert_entity_change(&1, 10), Changed::Inserted);
+    assert_eq!(index.insert_entity_change(&1, 10), Changed::AlreadyPresent);
+    assert_eq!(index.insert_entity_change(&1, 11), Changed::Inserted);
+  }
+
+  #[test]
+  fn remove_entity_change_variants() {
+    let mut index: Index<u32> = Index::new();
+    let _ = index.insert_entity(&1, 10);
+    let _ = index.insert_entity(&1, 11);
+
+    assert_eq!(index.remove_entity_change(&2, 10), Changed::NotFound);
+    assert_eq!(index.remove_entity_change(&1, 12), Changed::NotFound);
+    assert_eq!(index.remove_entity_change(&1, 10), Changed::Removed);
+    assert_eq!(index.remove_entity_change(&1, 10), Changed::NotFound);
+    assert!(index.contains_key(&1));
+
+    // Removing the last member drops the key.
+    assert_eq!(index.remove_entity_change(&1, 11), Changed::Removed);
+    assert!(!index.contains_key(&1));
+  }
+
+  #[test]
+  fn replace_set_returns_previous_set() {
+    let mut index: Index<u32> = Index::new();
+    assert_eq!(index.replace_set(&1, HashSet::from([10, 11])), None);
+    assert_eq!(index.get(&1), Some(&HashSet::from([10, 11])));
+
+    assert_eq!(index.replace_set(&1, HashSet::from([12])), Some(HashSet::from([10, 11])));
+    assert_eq!(index.get(&1), Some(&HashSet::from([12])));
+    // The key was replaced, not duplicated.
+    assert_eq!(index.entity_count(), 1);
+  }
+
+  #[test]
+  fn get_key_value_returns_stored_key() {
+    let mut index: Index<String> = Index::new();
+    let stored = "canonical".to_string();
+    let _ = index.insert_entity(&stored, 1);
+
+    let query = "canonical".to_string();
+    let (key, set) = index.get_key_value(&query).unwrap();
+    assert_eq!(key, &query);
+    // The returned key is the index's own copy, not the query.
+    assert_ne!(key.as_ptr(), query.as_ptr());
+    assert_eq!(set, &HashSet::from([1]));
+
+    assert!(index.get_key_value(&"other".to_string()).is_none());
+  }
+
+  #[test]
+  fn get_mut_with_hash_mutates_bucket() {
+    let mut index: Index<u32> = Index::new();
+    let _ = index.insert_entity(&7, 1);
+
+    let hash = one_shot_128(&7u32);
+    index.get_mut_with_hash(hash).unwrap().insert(2);
+    assert_eq!(index.get(&7), Some(&HashSet::from([1, 2])));
+
+    assert!(index.get_mut_with_hash(one_shot_128(&8u32)).is_none());
+  }
+
+  #[test]
+  fn entity_count_sums_bucket_sizes() {
+    let mut index: Index<u32> = Index::new();
+    assert_eq!(index.entity_count(), 0);
+
+    let _ = index.insert_entity(&1, 10);
+    let _ = index.insert_entity(&1, 11);
+    let _ = index.insert_entity(&2, 10);
+    assert_eq!(index.entity_count(), 3);
+  }
+
+  #[test]
+  fn drain_entities_by_id_threshold() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"a", 1);
+    let _ = index.insert_entity(&"a", 5);
+    let _ = index.insert_entity(&"b", 6);
+    let _ = index.insert_entity(&"b", 7);
+    let _ = index.insert_entity(&"c", 2);
+
+    let mut drained = index.drain_entities(|_, entity_id| entity_id > 4);
+    drained.sort();
+
+    assert_eq!(drained, vec![("a", 5), ("b", 6), ("b", 7)]);
+    assert_eq!(index.get(&"a"), Some(&HashSet::from([1])));
+    assert!(!index.contains_key(&"b"));
+    assert_eq!(index.get(&"c"), Some(&HashSet::from([2])));
+  }
+
+  #[test]
+  fn retain_entities_restricts_to_cohort() {
+    let mut index: Index<u32> = Index::new();
+    let _ = index.insert_entity(&1, 10);
+    let _ = index.insert_entity(&1, 11);
+    let _ = index.insert_entity(&2, 12);
+    let _ = index.insert_entity(&3, 13);
+    let _ = index.insert_entity(&3, 14);
+
+    index.retain_entities(&HashSet::from([11, 14]));
+
+    assert_eq!(index.get(&1), Some(&HashSet::from([11])));
+    assert!(!index.contains_key(&2));
+    assert_eq!(index.get(&3), Some(&HashSet::from([14])));
+    assert_eq!(index.entity_count(), 2);
+  }
+
+  #[test]
+  fn validate_with_prunes_stale_entities() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"susceptible", 1);
+    let _ = index.insert_entity(&"susceptible", 2);
+    let _ = index.insert_entity(&"infected", 3);
+    let _ = index.insert_entity(&"recovered", 4);
+
+    // Entities 2 and 3 have left the simulation.
+    assert_eq!(index.validate_with(&HashSet::from([1, 4])), 2);
+    assert_eq!(index.get(&"susceptible"), Some(&HashSet::from([1])));
+    assert!(!index.contains_key(&"infected"));
+    assert_eq!(index.get(&"recovered"), Some(&HashSet::from([4])));
+
+    assert_eq!(index.validate_with(&HashSet::from([1, 4])), 0);
+  }
+
+  #[test]
+  fn merge_hashes_unions_matching_keys() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"a", 1);
+    let _ = index.insert_entity(&"b", 2);
+
+    let erased = vec![
+      (one_shot_128(&"a"), HashSet::from([3, 4])),
+      (one_shot_128(&"b"), HashSet::from([2, 5])),
+    ];
+    assert_eq!(index.merge_hashes(erased), Ok(()));
+    assert_eq!(index.get(&"a"), Some(&HashSet::from([1, 3, 4])));
+    assert_eq!(index.get(&"b"), Some(&HashSet::from([2, 5])));
+
+    let unknown = one_shot_128(&"c");
+    assert_eq!(index.merge_hashes(vec![(unknown, HashSet::from([6]))]), Err(unknown));
+    assert!(!index.contains_key(&"c"));
+  }
+
+  #[test]
+  fn to_columns_expands_memberships() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"a", 1);
+    let _ = index.insert_entity(&"a", 2);
+    let _ = index.insert_entity(&"b", 3);
+
+    let (keys, entities) = index.to_columns();
+    assert_eq!(keys.len(), entities.len());
+    assert_eq!(keys.len(), index.entity_count());
+
+    let mut rows: Vec<_> = keys.into_iter().zip(entities).collect();
+    rows.sort();
+    assert_eq!(rows, vec![("a", 1), ("a", 2), ("b", 3)]);
+  }
+
+  #[test]
+  fn keys_and_entities_matching() {
+    let mut index: Index<u32> = Index::new();
+    for entity_id in 0..5 {
+      let _ = index.insert_entity(&1, entity_id);
+    }
+    for entity_id in 0..3 {
+      let _ = index.insert_entity(&2, entity_id);
+    }
+    let _ = index.insert_entity(&3, 0);
+
+    let large = |_: &u32, set: &HashSet<PersonId>| set.len() > 2;
+    assert_eq!(index.keys_matching(large), 2);
+    assert_eq!(index.entities_matching(large), 8);
+
+    let odd = |key: &u32, _: &HashSet<PersonId>| key % 2 == 1;
+    assert_eq!(index.keys_matching(odd), 2);
+    assert_eq!(index.entities_matching(odd), 6);
+  }
+
+  #[test]
+  fn fingerprint_is_order_independent() {
+    let mut a: Index<u32> = Index::new();
+    let _ = a.insert_entity(&1, 10);
+    let _ = a.insert_entity(&1, 11);
+    let _ = a.insert_entity(&2, 20);
+
+    let mut b: Index<u32> = Index::new();
+    let _ = b.insert_entity(&2, 20);
+    let _ = b.insert_entity(&1, 11);
+    let _ = b.insert_entity(&1, 10);
+
+    assert_eq!(a.fingerprint(), b.fingerprint());
+
+    let _ = b.insert_entity(&2, 21);
+    assert_ne!(a.fingerprint(), b.fingerprint());
+
+    let mut erased = crate::type_erased_index::Index::new();
+    erased.insert(2u32, HashSet::from([20]));
+    erased.insert(1u32, HashSet::from([10, 11]));
+    assert_eq!(a.fingerprint(), erased.fingerprint());
+  }
+
+  #[cfg(feature = "rand")]
+  #[test]
+  fn sample_entities_from_bucket() {
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+
+    let mut index: Index<u32> = Index::new();
+    for entity_id in 0..10 {
+      let _ = index.insert_entity(&1, entity_id);
+    }
+    index.replace_set(&2, HashSet::new());
+
+    for _ in 0..20 {
+      let sampled = index.sample_entity(&1, &mut rng).unwrap();
+      assert!(index.get(&1).unwrap().contains(&sampled));
+    }
+    assert_eq!(index.sample_entity(&2, &mut rng), None);
+    assert_eq!(index.sample_entity(&3, &mut rng), None);
+
+    let sampled = index.sample_entities(&1, 4, &mut rng);
+    assert_eq!(sampled.len(), 4);
+    assert_eq!(sampled.iter().collect::<HashSet<_>>().len(), 4);
+    assert_eq!(index.sample_entities(&1, 20, &mut rng).len(), 10);
+    assert!(index.sample_entities(&3, 2, &mut rng).is_empty());
+  }
+
+  #[test]
+  fn min_and_max_key() {
+    let mut index: Index<i32> = Index::new();
+    assert_eq!(index.min_key(), None);
+    assert_eq!(index.max_key(), None);
+
+    for key in [5, -3, 12, 0] {
+      let _ = index.insert_entity(&key, 1);
+    }
+    assert_eq!(index.min_key(), Some(&-3));
+    assert_eq!(index.max_key(), Some(&12));
+  }
+
+  #[test]
+  fn as_map_view() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"a", 1);
+    let _ = index.insert_entity(&"a", 2);
+    let _ = index.insert_entity(&"b", 3);
+
+    let map = index.as_map();
+    assert_eq!(map.len(), 2);
+    assert!(!map.is_empty());
+    assert_eq!(map.get(&"a"), Some(&HashSet::from([1, 2])));
+    assert_eq!(map.get(&"c"), None);
+    assert!(map.contains_key(&"b"));
+
+    let mut pairs: Vec<_> = map.iter().map(|(key, set)| (*key, set.len())).collect();
+    pairs.sort();
+    assert_eq!(pairs, vec![("a", 2), ("b", 1)]);
+  }
+
+  #[test]
+  fn intersection_counts_cross_tabulates() {
+    let mut status: Index<&'static str> = Index::new();
+    let mut region: Index<u8> = Index::new();
+    for (id, s) in [(1, "infected"), (2, "infected"), (3, "recovered"), (4, "recovered"), (5, "recovered")] {
+      let _ = status.insert_entity(&s, id);
+    }
+    for (id, r) in [(1, 1), (2, 2), (3, 1), (4, 1), (5, 2)] {
+      let _ = region.insert_entity(&r, id);
+    }
+
+    let counts = intersection_counts(&status, &region);
+    assert_eq!(counts.len(), 4);
+    assert_eq!(counts[&("infected", 1)], 1);
+    assert_eq!(counts[&("infected", 2)], 1);
+    assert_eq!(counts[&("recovered", 1)], 2);
+    assert_eq!(counts[&("recovered", 2)], 1);
+
+    // Disjoint pairs are reported as zero rather than omitted.
+    let _ = region.insert_entity(&3, 6);
+    assert_eq!(intersection_counts(&status, &region)[&("infected", 3)], 0);
+  }
+
+  #[test]
+  fn entry_vacant_and_occupied() {
+    let mut index: Index<&'static str> = Index::new();
+
+    // Vacant: `and_modify` does nothing and `or_insert` stores the given set.
+    let set = index.entry("infected").and_modify(|set| { set.insert(99); }).or_insert(HashSet::from([1]));
+    set.insert(2);
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1, 2])));
+
+    // Occupied: `and_modify` runs and `or_insert` keeps the existing set.
+    index.entry("infected").and_modify(|set| { set.insert(3); }).or_insert(HashSet::from([100]));
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1, 2, 3])));
+
+    assert!(index.entry("recovered").or_default().is_empty());
+    assert!(index.contains_key(&"recovered"));
+    index.entry("recovered").or_insert_with(|| unreachable!()).insert(4);
+    assert_eq!(index.get(&"recovered"), Some(&HashSet::from([4])));
+
+    assert!(matches!(index.entry("exposed"), Entry::Vacant(_)));
+    assert_eq!(index.entry("exposed").key(), &"exposed");
+    assert!(matches!(index.entry("infected"), Entry::Occupied(_)));
+  }
+
+  #[test]
+  fn are_disjoint_detects_overlap() {
+    let mut index: Index<&'static str> = Index::new();
+    for (id, key) in [(1, "susceptible"), (2, "susceptible"), (3, "infected"), (4, "recovered")] {
+      let _ = index.insert_entity(&key, id);
+    }
+
+    assert!(index.are_disjoint(&["susceptible", "infected", "recovered"]));
+    // Absent and repeated keys do not introduce overlaps.
+    assert!(index.are_disjoint(&["susceptible", "susceptible", "exposed"]));
+    assert!(index.are_disjoint(&[]));
+
+    let _ = index.insert_entity(&"recovered", 2);
+    assert!(!index.are_disjoint(&["susceptible", "infected", "recovered"]));
+    assert!(index.are_disjoint(&["infected", "recovered"]));
+  }
+
+  #[test]
+  fn bucket_sizes_sorted_is_ascending() {
+    let mut index: Index<u32> = Index::new();
+    for (key, size) in [(0, 3), (1, 1), (2, 5), (3, 1)] {
+      for entity_id in 0..size {
+        let _ = index.insert_entity(&key, entity_id);
+      }
+    }
+
+    let sizes = index.bucket_sizes_sorted();
+    assert_eq!(sizes.len(), index.len());
+    assert_eq!(sizes, vec![1, 1, 3, 5]);
+  }
+
+  #[test]
+  fn clone_into_copies_and_decouples() {
+    let mut src: Index<&'static str> = Index::new();
+    let _ = src.insert_entity(&"infected", 1);
+    let _ = src.insert_entity(&"infected", 2);
+    let _ = src.insert_entity(&"recovered", 3);
+
+    let mut dst: Index<&'static str> = Index::new();
+    let _ = dst.insert_entity(&"infected", 9);
+    let _ = dst.insert_entity(&"exposed", 8);
+
+    src.clone_into(&mut dst);
+    assert_eq!(dst.fingerprint(), src.fingerprint());
+    assert_eq!(dst.get(&"infected"), Some(&HashSet::from([1, 2])));
+    assert_eq!(dst.get(&"recovered"), Some(&HashSet::from([3])));
+    assert!(!dst.contains_key(&"exposed"));
+
+    let _ = src.insert_entity(&"infected", 4);
+    let _ = src.insert_entity(&"exposed", 5);
+    assert_eq!(dst.get(&"infected"), Some(&HashSet::from([1, 2])));
+    assert!(!dst.contains_key(&"exposed"));
+
+    dst.clone_from(&src);
+    assert_eq!(dst.fingerprint(), src.fingerprint());
+  }
+
+  #[test]
+  fn merge_existing_ignores_novel_keys() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"recovered", 2);
+
+    let mut other: Index<&'static str> = Index::new();
+    let _ = other.insert_entity(&"infected", 3);
+    let _ = other.insert_entity(&"recovered", 2);
+    let _ = other.insert_entity(&"exposed", 4);
+
+    assert_eq!(index.merge_existing(&other), 2);
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1, 3])));
+    assert_eq!(index.get(&"recovered"), Some(&HashSet::from([2])));
+    assert!(!index.contains_key(&"exposed"));
+  }
+
+  #[test]
+  fn remap_ids_rewrites_members() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"infected", 10);
+    let _ = index.insert_entity(&"infected", 20);
+    let _ = index.insert_entity(&"recovered", 30);
+    index.insert_value("exposed", HashSet::new());
+    let mapping = HashMap::from([(10, 0), (20, 1)]);
+
+    let mut kept = index.clone();
+    kept.remap_ids(&mapping, UnmappedIds::Keep);
+    assert_eq!(kept.get(&"infected"), Some(&HashSet::from([0, 1])));
+    assert_eq!(kept.get(&"recovered"), Some(&HashSet::from([30])));
+
+    index.remap_ids(&mapping, UnmappedIds::Remove);
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([0, 1])));
+    assert!(!index.contains_key(&"recovered"));
+    // A set that was already empty is not one the remap emptied.
+    assert_eq!(index.get(&"exposed"), Some(&HashSet::new()));
+  }
+
+  #[test]
+  fn key_for_entity_scans_sets() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"recovered", 2);
+    let _ = index.insert_entity(&"vaccinated", 2);
+
+    assert_eq!(index.key_for_entity(1), Some(&"infected"));
+    assert_eq!(index.key_for_entity(3), None);
+
+    let mut keys = index.keys_for_entity(2);
+    keys.sort_unstable();
+    assert_eq!(keys, vec![&"recovered", &"vaccinated"]);
+    assert!(index.keys_for_entity(3).is_empty());
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serde_round_trip_preserves_keys() {
+    let mut index: Index<String> = Index::new();
+    let _ = index.insert_entity(&"infected".to_string(), 1);
+    let _ = index.insert_entity(&"infected".to_string(), 2);
+    let _ = index.insert_entity(&"recovered".to_string(), 3);
+
+    let json = serde_json::to_string(&index).unwrap();
+    let loaded: Index<String> = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.fingerprint(), index.fingerprint());
+    assert_eq!(loaded.get(&"infected".to_string()), Some(&HashSet::from([1, 2])));
+
+    // Duplicate keys are merged instead of creating a second bucket.
+    let loaded: Index<String> = serde_json::from_str(r#"[0,[["infected",[1]],["infected",[2]]]]"#).unwrap();
+    assert_eq!(loaded.get(&"infected".to_string()), Some(&HashSet::from([1, 2])));
+    assert_eq!(loaded.len(), 1);
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serde_round_trip_preserves_seed() {
+    let mut index: Index<String> = Index::with_seed(7);
+    let _ = index.insert_entity(&"infected".to_string(), 1);
+    let hash = index.hash_of_key(&"infected".to_string());
+
+    let json = serde_json::to_string(&index).unwrap();
+    let loaded: Index<String> = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.seed(), 7);
+    assert_eq!(loaded.fingerprint(), index.fingerprint());
+    assert_eq!(loaded.get_with_hash(hash), Some(&HashSet::from([1])));
+
+    let loaded: Index<String> = serde_json::from_str(&serde_json::to_string(&Index::<String>::new()).unwrap()).unwrap();
+    assert_eq!(loaded.seed(), 0);
+  }
+
+  #[test]
+  fn union_into_folds_buckets() {
+    let mut index: Index<u8> = Index::new();
+    for (key, entity_id) in [(1, 10), (1, 11), (2, 11), (2, 12), (3, 13), (4, 14)] {
+      let _ = index.insert_entity(&key, entity_id);
+    }
+
+    let mut union = HashSet::new();
+    for key in [1, 2, 3, 5] {
+      index.union_into(&key, &mut union);
+    }
+    assert_eq!(union, HashSet::from([10, 11, 12, 13]));
+  }
+
+  #[test]
+  fn approx_memory_bytes_grows_with_entities() {
+    let mut index: Index<u32> = Index::new();
+    assert_eq!(index.approx_memory_bytes(), 0);
+
+    let _ = index.insert_entity(&0, 0);
+    let small = index.approx_memory_bytes();
+    assert!(small > 0);
+    for entity_id in 0..1000 {
+      let _ = index.insert_entity(&((entity_id % 10) as u32), entity_id);
+    }
+    assert!(index.approx_memory_bytes() > small);
+  }
+
+  #[test]
+  fn size_threshold_report_splits_by_size() {
+    let mut index: Index<u32> = Index::new();
+    for (key, size) in [(0, 1), (1, 2), (2, 3), (3, 8)] {
+      for entity_id in 0..size {
+        let _ = index.insert_entity(&key, entity_id);
+      }
+    }
+
+    assert_eq!(index.size_threshold_report(2), (2, 2));
+    assert_eq!(index.size_threshold_report(0), (0, 4));
+    assert_eq!(index.size_threshold_report(8), (4, 0));
+  }
+
+  #[test]
+  fn try_reserve_small_succeeds() {
+    let mut index: Index<u32> = Index::new();
+    assert!(index.try_reserve(100).is_ok());
+    assert!(index.lookup.capacity() >= 100);
+    assert!(index.try_reserve(usize::MAX).is_err());
+  }
+
+  #[test]
+  fn derive_indexable_indexes_every_field() {
+    #[derive(ixa_derive::Indexable)]
+    struct Person {
+      age: u8,
+      region: String,
+    }
+
+    let mut indexes = PersonIndexes::new();
+    indexes.insert(1, &Person { age: 30, region: "north".to_string() });
+    indexes.insert(2, &Person { age: 30, region: "south".to_string() });
+
+    assert_eq!(indexes.age.get(&30), Some(&HashSet::from([1, 2])));
+    assert_eq!(indexes.region.get(&"south".to_string()), Some(&HashSet::from([2])));
+  }
+
+  #[cfg(feature = "rayon")]
+  #[test]
+  fn par_iter_matches_serial_total() {
+    use rayon::iter::ParallelIterator;
+    let mut index: Index<u32> = Index::new();
+    for entity_id in 0..1000 {
+      let _ = index.insert_entity(&((entity_id % 37) as u32), entity_id);
+    }
+
+    let borrowed: usize = index.par_iter().map(|(_, set)| set.len()).sum();
+    let owned: usize = index.par_iter_owned().map(|(_, set)| set.len()).sum();
+    assert_eq!(borrowed, index.entity_count());
+    assert_eq!(owned, index.entity_count());
+  }
+
+  #[test]
+  fn rename_keys_merges_collisions() {
+    let mut index: Index<String> = Index::new();
+    let _ = index.insert_entity(&"infected".to_string(), 1);
+    let _ = index.insert_entity(&"Infected".to_string(), 2);
+    let _ = index.insert_entity(&"recovered".to_string(), 3);
+
+    index.rename_keys(|key| key.to_uppercase());
+    assert_eq!(index.len(), 2);
+    assert_eq!(index.get(&"INFECTED".to_string()), Some(&HashSet::from([1, 2])));
+    assert_eq!(index.get(&"RECOVERED".to_string()), Some(&HashSet::from([3])));
+    assert!(!index.contains_key(&"infected".to_string()));
+  }
+
+  #[test]
+  fn remove_entity_detailed_reports_outcome() {
+    let mut index: Index<&'static str> = Index::new();
+    for entity_id in [1, 2, 3] {
+      let _ = index.insert_entity(&"infected", entity_id);
+    }
+
+    assert_eq!(
+      index.remove_entity_detailed(&"infected", 2),
+      Some(RemoveOutcome { was_present: true, remaining: 2, key_dropped: false })
+    );
+    assert_eq!(
+      index.remove_entity_detailed(&"infected", 2),
+      Some(RemoveOutcome { was_present: false, remaining: 2, key_dropped: false })
+    );
+    assert_eq!(index.remove_entity_detailed(&"recovered", 1), None);
+
+    index.remove_entity_detailed(&"infected", 1);
+    assert_eq!(
+      index.remove_entity_detailed(&"infected", 3),
+      Some(RemoveOutcome { was_present: true, remaining: 0, key_dropped: true })
+    );
+    assert!(!index.contains_key(&"infected"));
+    assert_eq!(index.remove_entity_detailed(&"infected", 3), None);
+  }
+
+  #[test]
+  fn unique_entities_deduplicates() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+    let _ = index.insert_entity(&"hospitalized", 2);
+    let _ = index.insert_entity(&"recovered", 3);
+
+    let mut entities: Vec<PersonId> = index.unique_entities().collect();
+    entities.sort_unstable();
+    assert_eq!(entities, vec![1, 2, 3]);
+  }
+
+  #[test]
+  fn diff_reports_each_category() {
+    let mut before: Index<&'static str> = Index::new();
+    let _ = before.insert_entity(&"infected", 1);
+    let _ = before.insert_entity(&"infected", 2);
+    let _ = before.insert_entity(&"exposed", 3);
+    let _ = before.insert_entity(&"recovered", 4);
+
+    let mut after: Index<&'static str> = Index::new();
+    let _ = after.insert_entity(&"infected", 2);
+    let _ = after.insert_entity(&"infected", 5);
+    let _ = after.insert_entity(&"recovered", 4);
+    let _ = after.insert_entity(&"dead", 6);
+
+    let diff = before.diff(&after);
+    assert_eq!(diff.only_in_self, vec!["exposed"]);
+    assert_eq!(diff.only_in_other, vec!["dead"]);
+    assert_eq!(diff.changed, vec![KeyDiff { key: "infected", added: vec![5], removed: vec![1] }]);
+    assert_eq!(diff.to_string(), "- \"exposed\"\n+ \"dead\"\n~ \"infected\": added [5], removed [1]\n");
+
+    assert!(before.diff(&before).is_empty());
+  }
+
+  #[test]
+  fn hash_of_key_matches_get_with_hash() {
+    let mut index: Index<String> = Index::new();
+    let key = "infected".to_string();
+    let _ = index.insert_entity(&key, 1);
+
+    let hash = index.hash_of_key(&key);
+    assert_eq!(index.get_with_hash(hash), index.get(&key));
+    assert!(index.get_with_hash(hash).is_some());
+    assert!(index.get_mut_with_hash(hash).is_some());
+
+    let mut seeded: Index<String> = Index::with_seed(7);
+    let _ = seeded.insert_entity(&key, 1);
+    let hash = seeded.hash_of_key(&key);
+    assert_ne!(hash, index.hash_of_key(&key));
+    assert_eq!(seeded.get_with_hash(hash), Some(&HashSet::from([1])));
+    assert!(seeded.get_mut_with_hash(hash).is_some());
+  }
+
+  #[test]
+  fn overlap_matrix_counts_co_occurrences() {
+    let mut index: Index<&'static str> = Index::new();
+    for entity_id in [1, 2, 3] {
+      let _ = index.insert_entity(&"smoker", entity_id);
+    }
+    for entity_id in [2, 3, 4, 5] {
+      let _ = index.insert_entity(&"diabetic", entity_id);
+    }
+    let _ = index.insert_entity(&"asthmatic", 3);
+
+    let (keys, matrix) = index.overlap_matrix();
+    let position = |key: &str| keys.iter().position(|k| *k == key).unwrap();
+    let (smoker, diabetic, asthmatic) = (position("smoker"), position("diabetic"), position("asthmatic"));
+
+    assert_eq!(matrix[smoker][smoker], 3);
+    assert_eq!(matrix[diabetic][diabetic], 4);
+    assert_eq!(matrix[asthmatic][asthmatic], 1);
+    assert_eq!(matrix[smoker][diabetic], 2);
+    assert_eq!(matrix[diabetic][smoker], 2);
+    assert_eq!(matrix[smoker][asthmatic], 1);
+    assert_eq!(matrix[diabetic][asthmatic], 1);
+  }
+
+  #[test]
+  fn from_grouped_checked_rejects_duplicates() {
+    let index = Index::from_grouped_checked([
+      ("infected", HashSet::from([1, 2])),
+      ("recovered", HashSet::from([3])),
+    ]).unwrap();
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1, 2])));
+    assert_eq!(index.get(&"recovered"), Some(&HashSet::from([3])));
+
+    let duplicate = Index::from_grouped_checked([
+      ("infected", HashSet::from([1])),
+      ("recovered", HashSet::from([2])),
+      ("infected", HashSet::from([3])),
+    ]);
+    assert_eq!(duplicate.unwrap_err(), "infected");
+  }
+
+  #[test]
+  fn merge_boxed_requires_matching_key_types() {
+    let mut a: Index<u32> = Index::new();
+    let _ = a.insert_entity(&1, 10);
+    let mut b: Index<u32> = Index::new();
+    let _ = b.insert_entity(&1, 11);
+    let _ = b.insert_entity(&2, 12);
+
+    let mut boxed: BxIndex = Box::new(a);
+    assert!(boxed.merge_boxed(Box::new(b)).is_ok());
+    assert_eq!(boxed.get_with_hash(one_shot_128(&1u32)), Some(&HashSet::from([10, 11])));
+    assert_eq!(boxed.get_with_hash(one_shot_128(&2u32)), Some(&HashSet::from([12])));
+
+    let mut mismatched: Index<u64> = Index::new();
+    let _ = mismatched.insert_entity(&1, 13);
+    let returned = boxed.merge_boxed(Box::new(mismatched)).unwrap_err();
+    assert_eq!(returned.value_type_id(), TypeId::of::<u64>());
+    assert_eq!(returned.entity_count(), 1);
+    assert_eq!(boxed.entity_count(), 3);
+  }
+
+  #[test]
+  fn forget_keys_keeps_hashes_and_sets() {
+    let mut index: Index<String> = Index::new();
+    let _ = index.insert_entity(&"infected".to_string(), 1);
+    let _ = index.insert_entity(&"recovered".to_string(), 2);
+    let fingerprint = index.fingerprint();
+
+    let erased = index.forget_keys();
+    assert_eq!(erased.fingerprint(), fingerprint);
+    assert_eq!(erased.get(&"infected".to_string()), Some(&HashSet::from([1])));
+    assert_eq!(erased.seed(), 0);
+  }
+
+  #[test]
+  fn with_seed_hashes_keys_with_the_seed() {
+    let mut index: Index<&'static str> = Index::with_seed(7);
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"recovered", 2);
+    assert_eq!(index.seed(), 7);
+    assert_eq!(Index::<&'static str>::new().seed(), 0);
+
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1])));
+    assert_eq!(index.get_with_hash(one_shot_128_seeded(&"infected", 7)), Some(&HashSet::from([1])));
+    assert_eq!(index.get_with_hash(one_shot_128(&"infected")), None);
+
+    // The seed survives the conversions that rebuild the table.
+    let thawed = index.clone().freeze().thaw();
+    assert_eq!(thawed.seed(), 7);
+    assert_eq!(thawed.get(&"recovered"), Some(&HashSet::from([2])));
+    let (infected, _) = index.clone().split_by(|key| *key == "infected");
+    assert_eq!(infected.seed(), 7);
+
+    let mut unseeded: Index<&'static str> = Index::new();
+    let _ = unseeded.insert_entity(&"exposed", 3);
+    index.clone_into(&mut unseeded);
+    assert_eq!(unseeded.seed(), 7);
+    assert_eq!(unseeded.fingerprint(), index.fingerprint());
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn seed_round_trips_through_type_erased_serialization() {
+    let mut index: Index<&'static str> = Index::with_seed(7);
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+    let fingerprint = index.fingerprint();
+
+    let json = serde_json::to_string(&index.forget_keys()).unwrap();
+    let loaded: type_erased_index::Index = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.fingerprint(), fingerprint);
+
+    // A loader expecting the matching seed accepts the header and finds the keys.
+    assert_eq!(loaded.verify_seed(7), Ok(()));
+    assert_eq!(loaded.get(&"infected"), Some(&HashSet::from([1, 2])));
+
+    // An unseeded loader is told about the mismatch, and its hashes would miss.
+    assert_eq!(loaded.verify_seed(0), Err(7));
+    assert_eq!(loaded.get_with_hash(one_shot_128(&"infected")), None);
+
+    // Likewise an unseeded index is rejected by a loader expecting a seed.
+    let mut unseeded: Index<&'static str> = Index::new();
+    let _ = unseeded.insert_entity(&"infected", 1);
+    let json = serde_json::to_string(&unseeded.forget_keys()).unwrap();
+    let loaded: type_erased_index::Index = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.verify_seed(7), Err(0));
+    assert_eq!(loaded.get(&"infected"), Some(&HashSet::from([1])));
+  }
+
+  #[test]
+  fn forget_keys_merges_duplicate_hashes() {
+    let mut index: Index<&'static str> = Index::new();
+    index.insert_value("infected", HashSet::from([1]));
+    index.insert_value("infected", HashSet::from([2]));
+    index.insert_value("recovered", HashSet::from([3]));
+
+    let erased = index.forget_keys();
+    assert_eq!(erased.get(&"infected"), Some(&HashSet::from([1, 2])));
+    assert_eq!(erased.get(&"recovered"), Some(&HashSet::from([3])));
+    assert_eq!(erased.bucket_sizes_sorted(), vec![1, 2]);
+  }
+
+  #[test]
+  fn entity_degree_counts_containing_sets() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"smoker", 1);
+    let _ = index.insert_entity(&"diabetic", 1);
+    let _ = index.insert_entity(&"diabetic", 2);
+
+    assert_eq!(index.entity_degree(1), 2);
+    assert_eq!(index.entity_degree(2), 1);
+    assert_eq!(index.entity_degree(3), 0);
+  }
+
+  #[test]
+  fn bulk_contains_aligns_with_input() {
+    let mut index: Index<&'static str> = Index::new();
+    for entity_id in [2, 4, 6] {
+      let _ = index.insert_entity(&"infected", entity_id);
+    }
+
+    assert_eq!(index.bulk_contains(&"infected", &[1, 2, 6, 7, 4]), vec![false, true, true, false, true]);
+    assert_eq!(index.bulk_contains(&"recovered", &[2, 4]), vec![false, false]);
+    assert!(index.bulk_contains(&"infected", &[]).is_empty());
+  }
+
+  #[test]
+  fn remove_key_moves_set_out() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+    let _ = index.insert_entity(&"recovered", 3);
+
+    assert_eq!(index.remove_key(&"infected"), Some(HashSet::from([1, 2])));
+    assert_eq!(index.get(&"infected"), None);
+    assert!(!index.contains_key(&"infected"));
+    assert_eq!(index.remove_key(&"infected"), None);
+    assert_eq!(index.get(&"recovered"), Some(&HashSet::from([3])));
+  }
+
+  #[test]
+  fn full_hash_collisions_reports_distinct_keys_with_equal_hashes() {
+    // A key whose `Hash` ignores `variant`, so keys that differ only in `variant` have colliding hashes.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Lossy {
+      name: &'static str,
+      variant: u8,
+    }
+    impl Hash for Lossy {
+      fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+      }
+    }
+
+    let mut index: Index<Lossy> = Index::new();
+    index.insert_value(Lossy { name: "a", variant: 0 }, HashSet::from([1]));
+    index.insert_value(Lossy { name: "b", variant: 0 }, HashSet::from([2]));
+    assert!(index.full_hash_collisions().is_empty());
+
+    index.insert_value(Lossy { name: "a", variant: 1 }, HashSet::from([3]));
+    let collisions = index.full_hash_collisions();
+    assert_eq!(collisions.len(), 1);
+    let (first, second) = &collisions[0];
+    assert_eq!((first.name, second.name), ("a", "a"));
+    assert_ne!(first.variant, second.variant);
+  }
+
+  #[test]
+  fn bucket_ref_mutates_one_bucket() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"infected", 1);
+    assert!(index.bucket(&"recovered").is_none());
+
+    let mut bucket = index.bucket(&"infected").unwrap();
+    for entity_id in 2..=5 {
+      assert!(bucket.insert(entity_id));
+    }
+    assert!(!bucket.insert(3));
+    assert!(bucket.remove(1));
+    assert!(!bucket.remove(1));
+    assert!(bucket.contains(4));
+    assert_eq!(bucket.len(), 4);
+
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([2, 3, 4, 5])));
+  }
+
+  #[test]
+  fn shrink_if_sparse_only_shrinks_sparse_tables() {
+    let mut index: Index<u32> = Index::new();
+    for key in 0..1000 {
+      let _ = index.insert_entity(&key, 0);
+    }
+    assert!(!index.shrink_if_sparse(0.25));
+
+    for key in 10..1000 {
+      index.remove_key(&key);
+    }
+    let capacity = index.capacity();
+    assert!(index.shrink_if_sparse(0.25));
+    assert!(index.capacity() < capacity);
+    assert_eq!(index.get(&3), Some(&HashSet::from([0])));
+
+    // Now dense enough.
+    assert!(!index.shrink_if_sparse(0.25));
+    assert!(!Index::<u32>::new().shrink_if_sparse(0.25));
+  }
+
+  #[test]
+  fn keys_vec_and_entities_vec_are_owned_copies() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"infected", 2);
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"recovered", 3);
+
+    let mut keys = index.keys_vec();
+    assert_eq!(keys.len(), index.len());
+    keys.sort_unstable();
+    assert_eq!(keys, vec!["infected", "recovered"]);
+
+    let mut entities = index.entities_vec(&"infected").unwrap();
+    entities.sort_unstable();
+    assert_eq!(entities, vec![1, 2]);
+    assert_eq!(index.entities_vec(&"exposed"), None);
+  }
+
+  #[test]
+  fn clear_and_reserve_keeps_capacity() {
+    let mut index: Index<u32> = Index::new();
+    for key in 0..10 {
+      let _ = index.insert_entity(&key, 0);
+    }
+
+    index.clear_and_reserve(500);
+    assert!(index.is_empty());
+    assert_eq!(index.entity_count(), 0);
+    assert!(index.capacity() >= 500);
+
+    // A smaller request keeps the larger table.
+    let capacity = index.capacity();
+    index.clear_and_reserve(10);
+    assert_eq!(index.capacity(), capacity);
+  }
+
+  #[test]
+  fn merge_reporting_counts_each_outcome() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+    let _ = index.insert_entity(&"recovered", 3);
+
+    let mut other: Index<&'static str> = Index::new();
+    let _ = other.insert_entity(&"infected", 2);
+    let _ = other.insert_entity(&"infected", 4);
+    let _ = other.insert_entity(&"recovered", 5);
+    let _ = other.insert_entity(&"exposed", 6);
+    let _ = other.insert_entity(&"exposed", 7);
+
+    let report = index.merge_reporting(other);
+    assert_eq!(report, MergeReport { keys_created: 1, keys_updated: 2, entities_added: 4, entities_duplicate: 1 });
+    assert_eq!(index.get(&"infected"), Some(&HashSet::from([1, 2, 4])));
+    assert_eq!(index.get(&"exposed"), Some(&HashSet::from([6, 7])));
+  }
+
+  #[test]
+  fn remove_entity_evicts_emptied_set() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+
+    // Absent key and absent entity.
+    assert!(!index.remove_entity(&"recovered", 1));
+    assert!(!index.remove_entity(&"infected", 3));
+
+    assert!(index.remove_entity(&"infected", 1));
+    assert!(index.contains_key(&"infected"));
+    assert!(index.remove_entity(&"infected", 2));
+    assert!(!index.contains_key(&"infected"));
+    assert!(index.is_empty());
+    assert!(!index.remove_entity(&"infected", 2));
+  }
+
+  #[test]
+  fn len_counts_keys_not_entities() {
+    let mut index: Index<&'static str> = Index::new();
+    assert!(index.is_empty());
+    assert_eq!(index.len(), 0);
+
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+    let _ = index.insert_entity(&"recovered", 3);
+    assert!(!index.is_empty());
+    assert_eq!(index.len(), 2);
+    assert_eq!(index.entity_count(), 3);
+  }
+
+  #[test]
+  fn iter_yields_every_key_and_set() {
+    let mut index: Index<u32> = Index::new();
+    let _ = index.insert_entity(&2, 20);
+    let _ = index.insert_entity(&1, 10);
+    let _ = index.insert_entity(&1, 11);
+    let _ = index.insert_entity(&3, 30);
+
+    let mut pairs: Vec<(u32, HashSet<PersonId>)> = index.iter().map(|(key, set)| (*key, set.clone())).collect();
+    pairs.sort_unstable_by_key(|(key, _)| *key);
+    assert_eq!(pairs, vec![
+      (1, HashSet::from([10, 11])),
+      (2, HashSet::from([20])),
+      (3, HashSet::from([30])),
+    ]);
+  }
+
+  #[test]
+  fn iter_keys_yields_distinct_keys() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"susceptible", 1);
+    let _ = index.insert_entity(&"infected", 1);
+    let _ = index.insert_entity(&"infected", 2);
+    let _ = index.insert_entity(&"recovered", 2);
+
+    assert_eq!(index.iter_keys().count(), 3);
+    let mut keys = index.keys();
+    keys.sort_unstable();
+    assert_eq!(keys, vec!["infected", "recovered", "susceptible"]);
+  }
+
+  #[test]
+  fn entity_count_for_key() {
+    let mut index: Index<&'static str> = Index::new();
+    for entity_id in 0..25 {
+      let _ = index.insert_entity(&"infected", entity_id);
+    }
+    let _ = index.insert_entity(&"recovered", 100);
+
+    assert_eq!(index.entity_count_for(&"infected"), 25);
+    assert_eq!(index.entity_count_for(&"recovered"), 1);
+    assert_eq!(index.entity_count_for(&"exposed"), 0);
+  }
+
+  #[test]
+  fn clear_keeps_capacity() {
+    let mut index: Index<u32> = Index::new();
+    for key in 0..100 {
+      let _ = index.insert_entity(&key, key as PersonId);
+    }
+    let capacity = index.capacity();
+
+    index.clear();
+    assert!(index.is_empty());
+    assert!((0..100).all(|key| index.get(&key).is_none()));
+    assert_eq!(index.capacity(), capacity);
+
+    // The cleared index is usable again.
+    assert!(index.insert_entity(&7, 1));
+    assert_eq!(index.len(), 1);
+  }
+
+  #[test]
+  fn retain_drops_empty_sets() {
+    let mut index: Index<u32> = Index::new();
+    let _ = index.insert_entity(&1, 10);
+    let _ = index.insert_entity(&2, 20);
+    let _ = index.insert_entity(&2, 21);
+    // Empty a set in place, which leaves its key behind.
+    index.get_mut(&1).unwrap().clear();
+
+    index.retain(|_, set| !set.is_empty());
+    assert!(!index.contains_key(&1));
+    assert_eq!(index.get(&2), Some(&HashSet::from([20, 21])));
+  }
+
+  #[test]
+  fn retain_by_key_and_mutated_set() {
+    let mut index: Index<u32> = Index::new();
+    for key in 0..10u32 {
+      let _ = index.insert_entity(&key, key as PersonId);
+      let _ = index.insert_entity(&key, 100 + key as PersonId);
+    }
+
+    index.retain(|key, set| {
+      set.remove(&100);
+      key % 2 == 0
+    });
+    assert_eq!(index.len(), 5);
+    assert!((0..10u32).all(|key| index.contains_key(&key) == (key % 2 == 0)));
+    // The kept sets were modified, and lookups still find them.
+    assert_eq!(index.get(&0), Some(&HashSet::from([0])));
+    assert_eq!(index.get(&4), Some(&HashSet::from([4, 104])));
+  }
+
+  #[test]
+  fn into_iter_round_trips() {
+    let mut index: Index<&'static str> = Index::new();
+    let _ = index.insert_entity(&"susceptible", 1);
+    let _ = index.insert_entity(&"infected", 2);
+    let _ = index.insert_entity(&"infected", 3);
+    let fingerprint = index.fingerprint();
+
+    let mut rebuilt: Index<&'static str> = Index::new();
+    for (value, people) in index {
+      rebuilt.insert_value(value, people);
+    }
+    assert_eq!(rebuilt.len(), 2);
+    assert_eq!(rebuilt.get(&"infected"), Some(&HashSet::from([2, 3])));
+    assert_eq!(rebuilt.fingerprint(), fingerprint);
+  }
+
+  #[test]
+  fn merge_unions_shared_keys() {
+    let mut a: Index<u32> = Index::new();
+    let _ = a.insert_entity(&1, 10);
+    let _ = a.insert_entity(&2, 20);
+
+    let mut b: Index<u32> = Index::new();
+    let _ = b.insert_entity(&2, 20);
+    let _ = b.insert_entity(&2, 21);
+    let _ = b.insert_entity(&3, 30);
+
+    a.merge(b);
+
+    assert_eq!(a.get(&1), Some(&HashSet::from([10])));
+    assert_eq!(a.get(&2), Some(&HashSet::from([20, 21])));
+    assert_eq!(a.get(&3), Some(&HashSet::from([30])));
+  }
+
+  #[test]
+  fn split_by_partitions_keys() {
+    let mut index: Index<String> = Index::new();
+    let _ = index.insert_entity(&"north-east".to_string(), 1);
+    let _ = index.insert_entity(&"north-west".to_string(), 2);
+    let _ = index.insert_entity(&"north-west".to_string(), 3);
+    let _ = index.insert_entity(&"south".to_string(), 4);
+
+    let (north, rest) = index.split_by(|key| key.starts_with("north"));
+
+    assert_eq!(north.get(&"north-east".to_string()), Some(&HashSet::from([1])));
+    assert_eq!(north.get(&"north-west".to_string()), Some(&HashSet::from([2, 3])));
+    assert!(!north.contains_key(&"south".to_string()));
+
+    assert_eq!(rest.get(&"south".to_string()), Some(&HashSet::from([4])));
+    assert!(!rest.contains_key(&"north-east".to_string()));
+    assert!(!rest.contains_key(&"north-west".to_string()));
   }
 }
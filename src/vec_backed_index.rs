@@ -0,0 +1,112 @@
+/*!
+
+A typed index whose sets are sorted `Vec<PersonId>`s instead of `HashSet<PersonId>`s.
+
+**Performance profile compared to `Index<T>`:**
+
+- `get` returns a sorted slice, which allows indexed and random access (e.g. for contact sampling) and iterates
+  contiguously, which is much more cache friendly than walking a `HashSet`.
+- Membership tests are O(log n) binary searches instead of O(1) hash lookups.
+- Inserting an entity is O(n) in the size of its set, because later members are shifted to keep the set sorted. This
+  is the price of the above; prefer `Index<T>` for sets that are large and change often.
+
+*/
+
+use std::any::Any;
+use std::hash::Hash;
+use hashbrown::HashTable;
+use crate::hash128::one_shot_128;
+use crate::EntityId;
+
+type PersonId = EntityId;
+
+/// A typed index with sorted, deduplicated `Vec` sets.
+#[derive(Debug)]
+pub struct VecBackedIndex<T: Hash + Eq + Clone + Any> {
+  lookup: HashTable<(T, Vec<PersonId>)>,
+}
+
+impl<T: Hash + Eq + Clone + Any> Default for VecBackedIndex<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T: Hash + Eq + Clone + Any> VecBackedIndex<T> {
+  pub fn new() -> Self {
+    Self {
+      lookup: HashTable::new(),
+    }
+  }
+
+  /// Inserts an entity into the set associated with `key`, creating a new set if one does not yet exist. Returns a
+  /// `bool` according to whether the `entity_id` already existed in the set.
+  #[must_use]
+  pub fn insert_entity(&mut self, key: &T, entity_id: PersonId) -> bool {
+    let hash = one_shot_128(&key);
+
+    // > `hasher` is called if entries need to be moved or copied to a new table.
+    // > This must return the same hash value that each entry was inserted with.
+    let hasher = |(stored_value, _stored_set): &_| one_shot_128(stored_value) as u64;
+    // Equality is determined by comparing the full 128-bit hashes.
+    let hash128_equality = |(stored_value, _): &_| one_shot_128(stored_value) == hash;
+    let mut entry = self.lookup
+                        .entry(hash as u64, hash128_equality, hasher)
+                        .or_insert_with(|| (key.clone(), Vec::new()));
+    let set = &mut entry.get_mut().1;
+    match set.binary_search(&entity_id) {
+      Ok(_) => false,
+      Err(position) => {
+        set.insert(position, entity_id);
+        true
+      }
+    }
+  }
+
+  /// Gets the sorted members of the set associated with the `key` if it exists.
+  pub fn get(&self, key: &T) -> Option<&[PersonId]> {
+    let hash = one_shot_128(&key);
+    let hash128_equality = |(stored_value, _): &_| one_shot_128(stored_value) == hash;
+    self.lookup.find(hash as u64, hash128_equality).map(|(_, set)| set.as_slice())
+  }
+
+  /// Is `entity_id` in the set associated with `key`? This is a binary search.
+  pub fn contains_entity(&self, key: &T, entity_id: PersonId) -> bool {
+    self.get(key).is_some_and(|set| set.binary_search(&entity_id).is_ok())
+  }
+
+  pub fn contains_key(&self, key: &T) -> bool {
+    self.get(key).is_some()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sets_are_sorted() {
+    let mut index: VecBackedIndex<&'static str> = VecBackedIndex::new();
+    for entity_id in [5, 1, 9, 3, 7] {
+      assert!(index.insert_entity(&"contacts", entity_id));
+    }
+    assert_eq!(index.get(&"contacts"), Some(&[1, 3, 5, 7, 9][..]));
+    assert_eq!(index.get(&"contacts").unwrap()[2], 5);
+    assert_eq!(index.get(&"others"), None);
+  }
+
+  #[test]
+  fn inserts_are_deduplicated() {
+    let mut index: VecBackedIndex<u32> = VecBackedIndex::new();
+    assert!(index.insert_entity(&1, 4));
+    assert!(!index.insert_entity(&1, 4));
+    assert!(index.insert_entity(&1, 2));
+    assert!(!index.insert_entity(&1, 2));
+    assert_eq!(index.get(&1), Some(&[2, 4][..]));
+
+    assert!(index.contains_entity(&1, 2));
+    assert!(!index.contains_entity(&1, 3));
+    assert!(!index.contains_entity(&2, 2));
+    assert!(index.contains_key(&1));
+  }
+}